@@ -0,0 +1,102 @@
+use std::path::Path;
+
+use serde_json;
+use serde_json::value::Value as JsonValue;
+use toml;
+use toml::value::{Table, Value};
+
+use super::errors::*;
+use super::fsutils;
+use super::template::Params;
+
+/// File answers are saved into, at the top of generated project.
+pub const FILE_NAME: &'static str = ".rig-answers.toml";
+
+/// Read answers to parameters from TOML file, or JSON file if it ends with `.json`.
+///
+/// ```toml
+/// name = "hello"
+/// docker = true
+/// modules = ["core", "web"]
+/// ```
+pub fn read(path: &Path) -> Result<Vec<(String, Value)>> {
+    let content = fsutils::read_file(path)?;
+    let is_json = path.extension().map(|ext| ext == "json").unwrap_or(false);
+    if is_json {
+        from_json(&content)
+    } else {
+        from_toml(&content)
+    }
+}
+
+pub fn from_toml(content: &str) -> Result<Vec<(String, Value)>> {
+    let table: Table = toml::from_str(content).chain_err(|| ErrorKind::TomlDecodeFailure)?;
+    Ok(table.into_iter().collect())
+}
+
+pub fn from_json(content: &str) -> Result<Vec<(String, Value)>> {
+    match serde_json::from_str(content)? {
+        JsonValue::Object(map) => {
+            Ok(map.into_iter()
+                .filter_map(|(k, v)| from_json_value(v).map(|v| (k, v)))
+                .collect())
+        }
+        _ => Err("answers must be an object".into()),
+    }
+}
+
+/// Convert JSON value into TOML value. `null` has no counterpart, and is dropped.
+fn from_json_value(value: JsonValue) -> Option<Value> {
+    match value {
+        JsonValue::Null => None,
+        JsonValue::Bool(b) => Some(Value::Boolean(b)),
+        JsonValue::Number(n) => {
+            n.as_i64().map(Value::Integer).or(n.as_f64().map(Value::Float))
+        }
+        JsonValue::String(s) => Some(Value::String(s)),
+        JsonValue::Array(items) => {
+            Some(Value::Array(items.into_iter().filter_map(from_json_value).collect()))
+        }
+        JsonValue::Object(map) => {
+            Some(Value::Table(map.into_iter()
+                .filter_map(|(k, v)| from_json_value(v).map(|v| (k, v)))
+                .collect()))
+        }
+    }
+}
+
+/// Write values of `params` as TOML, in declaration order, which can be read back
+/// by `--answers` to generate the same project again.
+pub fn to_toml(params: &Params) -> Result<String> {
+    let mut values = String::new();
+    let mut tables = String::new();
+    for key in &params.keys {
+        let value = match params.get_value(key) {
+            Some(value) => value,
+            None => continue,
+        };
+
+        // TOML can only be written from table, and tables (including arrays of them)
+        // must come after other values, or the values would belong to them
+        let mut entry = Table::new();
+        entry.insert(key.clone(), value.clone());
+        let written = toml::to_string(&entry)
+            .chain_err(|| format!("Failed to write answer to `{}`", key))?;
+        if is_table(value) {
+            tables.push('\n');
+            tables.push_str(&written);
+        } else {
+            values.push_str(&written);
+        }
+    }
+    Ok(values + &tables)
+}
+
+/// Whether `value` is written as `[key]` or `[[key]]` section.
+fn is_table(value: &Value) -> bool {
+    match *value {
+        Value::Table(_) => true,
+        Value::Array(ref items) => items.first().map(|item| item.is_table()).unwrap_or(false),
+        _ => false,
+    }
+}
@@ -0,0 +1,174 @@
+use std::io::{Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+
+use curl::easy::Easy;
+use flate2::read::GzDecoder;
+use tar;
+use url::Url;
+use xz2::read::XzDecoder;
+use zip::ZipArchive;
+
+use super::errors::*;
+use super::fsutils;
+use super::git;
+use super::source::MemorySource;
+
+/// Archive formats templates can be published in.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Format {
+    TarGz,
+    TarXz,
+    Zip,
+}
+
+impl Format {
+    /// Guess format from file name, like `template-1.0.tar.gz`.
+    pub fn from_name(name: &str) -> Option<Format> {
+        let name = name.to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(Format::TarXz)
+        } else if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Download or read archive at `url`, and unpack it into memory.
+pub fn open(url: &Url) -> Result<MemorySource> {
+    let format = Format::from_name(url.path())
+        .ok_or(Error::from(ErrorKind::InvalidArchive(url.to_string())))?;
+    let content = fetch(url)?;
+    unpack(&content, format).chain_err(|| ErrorKind::InvalidArchive(url.to_string()))
+}
+
+/// Read content of `file://` or HTTP(S) URL.
+pub fn fetch(url: &Url) -> Result<Vec<u8>> {
+    if url.scheme() == "file" {
+        let path = url.to_file_path()
+            .map_err(|_| Error::from(ErrorKind::InvalidUrlFormat(url.to_string())))?;
+        return fsutils::read_bytes(&path).map_err(|e| e.into());
+    }
+
+    info!("Downloading archive: {}", url);
+    let mut content = Vec::new();
+    let mut easy = Easy::new();
+    download(&mut easy, url, &mut content)
+        .map_err(|e| Error::from(ErrorKind::DownloadFailure(format!("{}: {}", url, e))))?;
+    Ok(content)
+}
+
+fn download(easy: &mut Easy,
+            url: &Url,
+            content: &mut Vec<u8>)
+            -> ::std::result::Result<(), ::curl::Error> {
+    easy.url(url.as_str())?;
+    easy.follow_location(true)?;
+    easy.fail_on_error(true)?;
+    if let Some(proxy) = git::find_proxy_url(url) {
+        easy.proxy(proxy.as_str())?;
+    }
+
+    let mut transfer = easy.transfer();
+    transfer.write_function(|data| {
+            content.extend_from_slice(data);
+            Ok(data.len())
+        })?;
+    transfer.perform()
+}
+
+/// Unpack regular files in archive.
+///
+/// If all files are in a single directory, as in most release archives,
+/// that directory becomes the top of the source.
+pub fn unpack(content: &[u8], format: Format) -> Result<MemorySource> {
+    let files = match format {
+        Format::TarGz => read_tar(GzDecoder::new(content)?)?,
+        Format::TarXz => read_tar(XzDecoder::new(content))?,
+        Format::Zip => read_zip(content)?,
+    };
+
+    let common = common_dir(&files);
+    let mut source = MemorySource::new();
+    for (path, content) in files {
+        match common {
+            Some(ref top) => source.insert(path.strip_prefix(top).unwrap(), content),
+            None => source.insert(path, content),
+        };
+    }
+    Ok(source)
+}
+
+fn read_tar<R: Read>(reader: R) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+    let mut files = Vec::new();
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !entry.header().entry_type().is_file() {
+            debug!("Skipping {:?}, which is not a regular file", path);
+            continue;
+        }
+
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        if let Some(path) = sanitize(&path) {
+            files.push((path, content));
+        }
+    }
+    Ok(files)
+}
+
+fn read_zip(content: &[u8]) -> Result<Vec<(PathBuf, Vec<u8>)>> {
+    let mut files = Vec::new();
+    let mut archive = ZipArchive::new(Cursor::new(content))
+        .map_err(|e| Error::from(e.to_string()))?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| Error::from(e.to_string()))?;
+        if file.name().ends_with('/') {
+            continue;
+        }
+
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        if let Some(path) = sanitize(Path::new(file.name())) {
+            files.push((path, content));
+        }
+    }
+    Ok(files)
+}
+
+fn common_dir(files: &[(PathBuf, Vec<u8>)]) -> Option<PathBuf> {
+    let top = match files.first().and_then(|&(ref path, _)| path.iter().next()) {
+        Some(top) => PathBuf::from(top),
+        None => return None,
+    };
+    if files.iter().all(|&(ref path, _)| path.starts_with(&top) && path != &top) {
+        Some(top)
+    } else {
+        None
+    }
+}
+
+/// Reject paths pointing outside of archive, like `../foo` or `/etc/foo`.
+fn sanitize(path: &Path) -> Option<PathBuf> {
+    let mut sanitized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => sanitized.push(part),
+            Component::CurDir => {}
+            _ => {
+                warn!("Skipping {:?} in archive, which points outside of it", path);
+                return None;
+            }
+        }
+    }
+    if sanitized.as_os_str().is_empty() {
+        None
+    } else {
+        Some(sanitized)
+    }
+}
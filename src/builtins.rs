@@ -0,0 +1,72 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use toml::value::{Table, Value};
+
+/// Name templates refer to built-in variables by, like `rig.year`.
+pub const NAMESPACE: &'static str = "rig";
+
+/// Variables Rig provides to every template, besides its own parameters.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Builtins {
+    /// Current year, like `2017`.
+    pub year: i64,
+    /// Current date in `YYYY-MM-DD`, in UTC.
+    pub date: String,
+    /// Version of Rig.
+    pub version: String,
+    /// Name of the directory project is generated into.
+    pub output_dir_name: String,
+    /// Repository URL, archive URL or local path of the template.
+    pub template_url: String,
+    /// Commit the template is generated from, or empty if it is not from git repository.
+    pub template_commit: String,
+}
+
+impl Builtins {
+    pub fn new(output_dir: &Path, template_url: &str, template_commit: Option<String>) -> Builtins {
+        let days = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() / 86400)
+            .unwrap_or(0);
+        let (year, month, day) = civil_date(days as i64);
+
+        Builtins {
+            year: year,
+            date: format!("{:04}-{:02}-{:02}", year, month, day),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            output_dir_name: output_dir.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or(String::new()),
+            template_url: template_url.to_string(),
+            template_commit: template_commit.unwrap_or(String::new()),
+        }
+    }
+
+    /// Variables as table, which is given to templates as `rig`.
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        table.insert("year".into(), Value::Integer(self.year));
+        table.insert("date".into(), Value::String(self.date.clone()));
+        table.insert("version".into(), Value::String(self.version.clone()));
+        table.insert("output_dir_name".into(), Value::String(self.output_dir_name.clone()));
+        table.insert("template_url".into(), Value::String(self.template_url.clone()));
+        table.insert("template_commit".into(), Value::String(self.template_commit.clone()));
+        table
+    }
+}
+
+/// Year, month and day of the date `days` after 1970-01-01,
+/// from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn civil_date(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
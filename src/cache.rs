@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+
+use git2::Repository;
+use url::Url;
+use walkdir::{WalkDir, WalkDirIterator};
+
+use super::errors::*;
+use super::fsutils;
+use super::git;
+
+/// Persistent store of template repositories, kept as bare mirrors under `root`.
+///
+/// Each repository is placed at `<root>/<host>/<path>.git`, so the layout is readable by human.
+#[derive(Clone, Debug)]
+pub struct Cache {
+    pub root: PathBuf,
+}
+
+/// A repository found in cache.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub url: String,
+    pub path: PathBuf,
+}
+
+impl Cache {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Cache {
+        Cache { root: root.into() }
+    }
+
+    /// Where the mirror of `url` lives, whether it exists or not.
+    pub fn path_for(&self, url: &Url) -> PathBuf {
+        let mut host = url.host_str().unwrap_or("local").to_string();
+        if let Some(port) = url.port() {
+            host = format!("{}_{}", host, port);
+        }
+
+        let mut path = self.root.join(sanitize(&host));
+        let segments = url.path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).map(sanitize).collect::<Vec<_>>())
+            .unwrap_or(Vec::new());
+        for segment in &segments {
+            path.push(segment);
+        }
+
+        if !path.to_string_lossy().ends_with(".git") {
+            path.set_extension("git");
+        }
+        path
+    }
+
+    pub fn contains(&self, url: &Url) -> bool {
+        fsutils::exists(self.path_for(url).join("HEAD"))
+    }
+
+    /// Fetch updates of `url` into cache, cloning it first if not cached yet.
+    pub fn update(&self, url: &Url, settings: &git::Settings) -> Result<PathBuf> {
+        let path = self.path_for(url);
+        info!("Updating cache of {} at {:?}", url, path);
+        git::mirror(url, &path, settings)?;
+        Ok(path)
+    }
+
+    /// Path to cached mirror of `url`, without accessing network.
+    pub fn get(&self, url: &Url) -> Result<PathBuf> {
+        if self.contains(url) {
+            Ok(self.path_for(url))
+        } else {
+            Err(ErrorKind::NotCached(url.to_string()).into())
+        }
+    }
+
+    /// List repositories in cache.
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        if !fsutils::is_directory(&self.root) {
+            return Ok(entries);
+        }
+
+        let mut walker = WalkDir::new(&self.root).min_depth(1).into_iter();
+        while let Some(entry) = walker.next() {
+            let entry = entry.map_err(|e| Error::from(e.to_string()))?;
+            if !is_mirror(entry.path()) {
+                continue;
+            }
+            walker.skip_current_dir();
+
+            let repo = Repository::open(entry.path())?;
+            let url = repo.find_remote("origin")
+                .ok()
+                .and_then(|remote| remote.url().map(|s| s.to_string()))
+                .unwrap_or(String::new());
+            entries.push(Entry {
+                url: url,
+                path: entry.path().to_path_buf(),
+            });
+        }
+
+        entries.sort_by(|a, b| a.url.cmp(&b.url));
+        Ok(entries)
+    }
+
+    /// Remove cached mirror of `url`.
+    pub fn remove(&self, url: &Url) -> Result<()> {
+        let path = self.get(url)?;
+        fsutils::remove_dir(&path).map_err(|e| e.into())
+    }
+
+    /// Remove everything in cache.
+    pub fn clear(&self) -> Result<()> {
+        if fsutils::exists(&self.root) {
+            fsutils::remove_dir(&self.root)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_mirror(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".git") && fsutils::exists(path.join("HEAD"))
+}
+
+fn sanitize(segment: &str) -> String {
+    let s = segment.chars()
+        .map(|c| if c.is_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+        .collect::<String>();
+    if s.chars().all(|c| c == '.') {
+        s.replace(".", "_")
+    } else {
+        s
+    }
+}
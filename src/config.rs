@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+
+use toml;
+use toml::value::{Table, Value};
+
+use super::cache::Cache;
+use super::errors::*;
+use super::fsutils;
+use super::git;
+use super::location::Hosts;
+use super::maven;
+
+/// User level settings read from `$RIG_HOME/config.toml` (`~/.rig/config.toml` by default).
+///
+/// ```toml
+/// default_host = "gl"
+/// cache_dir = "~/.cache/rig"
+/// index = ["~/templates.json", "https://artifacts.example.com/rig/index.json"]
+///
+/// [hosts]
+/// corp = "https://gitlab.corp.example.com"
+///
+/// [aliases]
+/// svc = "gl:platform/service-template#v3"
+///
+/// [git]
+/// ssh_key = "~/.ssh/id_ed25519"
+/// token_env = "GITLAB_TOKEN"
+///
+/// [maven]
+/// repository = "https://nexus.example.com/repository/maven-public"
+/// local = "~/.m2/repository"
+///
+/// [defaults]
+/// author = "Jane Doe"
+/// organization = "com.example"
+/// license = "MIT"
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct UserConfig {
+    pub hosts: Hosts,
+    pub git: git::Settings,
+    /// Repositories giter8 `maven()` directives are resolved against.
+    pub maven: maven::Settings,
+    pub cache_dir: Option<PathBuf>,
+    /// Short names standing for template locations.
+    pub aliases: HashMap<String, String>,
+    /// Paths or URLs of template indexes `rig search` looks into.
+    pub indexes: Vec<String>,
+    /// Values of parameters recurring in templates, used in place of template defaults.
+    pub defaults: Table,
+}
+
+impl UserConfig {
+    /// Read user configuration, or use defaults if there is no configuration file.
+    pub fn load() -> Result<UserConfig> {
+        match config_path() {
+            Some(ref path) if fsutils::exists(path) => {
+                debug!("Reading user configuration: {:?}", path);
+                let content = fsutils::read_file(path)?;
+                UserConfig::from_toml(&content)
+            }
+            _ => Ok(UserConfig::default()),
+        }
+    }
+
+    pub fn from_toml(content: &str) -> Result<UserConfig> {
+        let tbl: Table = toml::from_str(content).chain_err(|| ErrorKind::TomlDecodeFailure)?;
+        let mut config = UserConfig::default();
+
+        if let Some(hosts) = tbl.get("hosts").and_then(|v| v.as_table()) {
+            for (prefix, base) in hosts {
+                match base.as_str() {
+                    Some(base) => config.hosts.insert(prefix, base),
+                    None => warn!("Ignoring host `{}`: base URL must be a string", prefix),
+                }
+            }
+        }
+
+        if let Some(default) = tbl.get("default_host").and_then(|v| v.as_str()) {
+            config.hosts.set_default(default)?;
+        }
+
+        config.cache_dir = tbl.get("cache_dir").and_then(|v| v.as_str()).map(expand_home);
+
+        if let Some(aliases) = tbl.get("aliases").and_then(|v| v.as_table()) {
+            for (name, location) in aliases {
+                match location.as_str() {
+                    Some(location) => {
+                        config.aliases.insert(name.to_string(), location.to_string());
+                    }
+                    None => warn!("Ignoring alias `{}`: location must be a string", name),
+                }
+            }
+        }
+
+        config.indexes = match tbl.get("index") {
+            Some(&toml::Value::String(ref index)) => vec![index.clone()],
+            Some(&toml::Value::Array(ref indexes)) => {
+                indexes.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect()
+            }
+            _ => Vec::new(),
+        };
+        for index in &mut config.indexes {
+            if index.starts_with('~') {
+                *index = expand_home(index).to_string_lossy().into_owned();
+            }
+        }
+
+        if let Some(git) = tbl.get("git").and_then(|v| v.as_table()) {
+            config.git.ssh_key = git.get("ssh_key")
+                .and_then(|v| v.as_str())
+                .map(expand_home);
+            config.git.token_env = git.get("token_env")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+        }
+
+        if let Some(defaults) = tbl.get("defaults").and_then(|v| v.as_table()) {
+            config.defaults = defaults.clone();
+        }
+
+        if let Some(maven) = tbl.get("maven").and_then(|v| v.as_table()) {
+            if let Some(repository) = maven.get("repository").and_then(|v| v.as_str()) {
+                config.maven.repository = repository.to_string();
+            }
+            config.maven.local = maven.get("local")
+                .and_then(|v| v.as_str())
+                .map(expand_home);
+        }
+
+        Ok(config)
+    }
+
+    /// Location the alias `raw` stands for, or `raw` itself if it is not an alias.
+    pub fn expand_alias<'a>(&'a self, raw: &'a str) -> &'a str {
+        match self.aliases.get(raw) {
+            Some(location) => {
+                debug!("Expanding alias `{}` into {}", raw, location);
+                location
+            }
+            None => raw,
+        }
+    }
+
+    /// Default values of parameters, where `author` and `email` fall back to
+    /// `user.name` and `user.email` of git configuration.
+    pub fn profile(&self, identity: &git::Identity) -> Table {
+        let mut profile = self.defaults.clone();
+        for &(key, ref value) in &[("author", &identity.name), ("email", &identity.email)] {
+            if let Some(ref value) = **value {
+                if !profile.contains_key(key) {
+                    profile.insert(key.to_string(), Value::String(value.clone()));
+                }
+            }
+        }
+        profile
+    }
+
+    /// Template cache, at `cache_dir` or `$RIG_HOME/cache`.
+    pub fn cache(&self) -> Cache {
+        let root = self.cache_dir
+            .clone()
+            .or_else(|| rig_home().map(|home| home.join("cache")))
+            .unwrap_or(env::temp_dir().join("rig-cache"));
+        Cache::new(root)
+    }
+}
+
+/// Directory to keep Rig's user files, `$RIG_HOME` or `~/.rig`.
+pub fn rig_home() -> Option<PathBuf> {
+    env::var_os("RIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|home| home.join(".rig")))
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    rig_home().map(|home| home.join("config.toml"))
+}
+
+/// Replace leading `~` with home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    if path == "~" || path.starts_with("~/") {
+        if let Some(home) = env::home_dir() {
+            return home.join(path[1..].trim_left_matches('/'));
+        }
+    }
+    PathBuf::from(path)
+}
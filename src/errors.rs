@@ -1,18 +1,148 @@
+use std::fmt;
 use std::io;
+use std::path::PathBuf;
+use git2;
+use semver;
+use serde_json;
 use url;
 
+/// Failure rendering a template, with where in the template it happened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderError {
+    /// Path of the template, or empty for inline templates.
+    pub path: PathBuf,
+    /// Line and column counted from 1, or 0 if the template engine does not tell.
+    pub line: usize,
+    pub column: usize,
+    /// The line where rendering failed.
+    pub snippet: String,
+    pub reason: String,
+}
+
+impl RenderError {
+    /// Error at `line` and `column` of template `body`.
+    pub fn new(path: PathBuf, body: &str, line: usize, column: usize, reason: String)
+        -> RenderError
+    {
+        let snippet = match line {
+            0 => String::new(),
+            n => body.lines().nth(n - 1).unwrap_or("").to_string(),
+        };
+        RenderError {
+            path: path,
+            line: line,
+            column: column,
+            snippet: snippet,
+            reason: reason,
+        }
+    }
+
+    /// Error at byte `offset` of template `body`.
+    pub fn at_offset(path: PathBuf, body: &str, offset: usize, reason: String) -> RenderError {
+        let before = &body[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let column = before[line_start..].chars().count() + 1;
+        RenderError::new(path, body, line, column, reason)
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = self.path.to_string_lossy();
+        let path = if path.is_empty() { "template".into() } else { path };
+        if self.line == 0 {
+            return write!(f, "Failed to render {}: {}", path, self.reason);
+        }
+
+        write!(f,
+               "Failed to render {} at line {}, column {}: {}",
+               path,
+               self.line,
+               self.column,
+               self.reason)?;
+        let margin = self.line.to_string().len();
+        let caret = self.snippet
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        write!(f,
+               "\n{} | {}\n{} | {}^",
+               self.line,
+               self.snippet,
+               " ".repeat(margin),
+               caret)
+    }
+}
+
 error_chain! {
 
     foreign_links {
+        Git(git2::Error);
         Io(io::Error);
+        Json(serde_json::Error);
         ParseUrl(url::ParseError);
+        ParseVersionReq(semver::ReqParseError);
     }
 
     errors {
+        DownloadFailure(s: String) {
+            description("Failed to download template archive")
+            display("Failed to download template archive: {}", s)
+        }
+        GitCommand(s: String) {
+            description("git command failed")
+            display("git command failed: {}", s)
+        }
+        InvalidArchive(s: String) {
+            description("Invalid template archive")
+            display("Invalid template archive: {}", s)
+        }
+        InvalidIndex(s: String) {
+            description("Invalid template index")
+            display("Invalid template index: {}", s)
+        }
+        InvalidParam(name: String, reason: String) {
+            description("Invalid parameter declaration")
+            display("Invalid parameter `{}`: {}", name, reason)
+        }
         InvalidUrlFormat(s: String) {
             description("Invalid URL format")
             display("Invalid URL format: {}", s)
         }
+        NoMatchingVersion(s: String) {
+            description("No tag in template repository matches the version requirement")
+            display("No tag in template repository matches the version requirement: {}", s)
+        }
+        NotCached(s: String) {
+            description("Template repository is not in cache")
+            display("Template repository is not in cache: {}", s)
+        }
+        PropertiesDecodeFailure(s: String) {
+            description("Failed decoding properties file")
+            display("Failed decoding properties file: {}", s)
+        }
+        RenderFailure(e: RenderError) {
+            description("Failed to render template")
+            display("{}", e)
+        }
+        RevisionNotFound(s: String) {
+            description("Revision not found in template repository")
+            display("Revision not found in template repository: {}", s)
+        }
+        TemplateNotFound(s: String) {
+            description("Template directory not found")
+            display("Template directory not found: {}", s)
+        }
+        UnresolvedDirective(s: String) {
+            description("Failed to resolve version directive")
+            display("Failed to resolve version directive: {}", s)
+        }
+        UnknownHost(s: String) {
+            description("Unknown git host prefix")
+            display("Unknown git host prefix: {}", s)
+        }
         TomlDecodeFailure {
             description("Failured decoding Toml string")
             display("Failured decoding Toml string")
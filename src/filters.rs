@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use serde_json::value::{Value, to_value};
-use tera::{ErrorKind, Result};
+use tera::{ErrorKind, Result, Tera};
 
 use super::format::{format, Formatter};
 
@@ -27,4 +27,17 @@ convert_tera_filter! {
     snake, "snake", SnakeCase;
     packaged, "packaged", DirectoryPath;
     random, "random", AddRandom
-}
\ No newline at end of file
+}
+/// Register filters above to `tera`, with the names templates use.
+pub fn register(tera: &mut Tera) {
+    tera.register_filter("decap", decap);
+    tera.register_filter("word", word);
+    tera.register_filter("hyphen", hyphen);
+    tera.register_filter("start", start);
+    tera.register_filter("Camel", upper_camel);
+    tera.register_filter("camel", lower_camel);
+    tera.register_filter("norm", norm);
+    tera.register_filter("snake", snake);
+    tera.register_filter("packaged", packaged);
+    tera.register_filter("random", random);
+}
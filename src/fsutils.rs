@@ -25,14 +25,28 @@ pub fn read_file(path: &Path) -> io::Result<String> {
     Ok(contents)
 }
 
+pub fn read_bytes(path: &Path) -> io::Result<Vec<u8>> {
+    let mut file = try!(fs::OpenOptions::new()
+        .read(true)
+        .open(path));
+
+    let mut contents = Vec::new();
+    try!(io::Read::read_to_end(&mut file, &mut contents));
+    Ok(contents)
+}
+
 pub fn write_file(path: &Path, contents: &str) -> io::Result<()> {
+    write_bytes(path, contents.as_bytes())
+}
+
+pub fn write_bytes(path: &Path, contents: &[u8]) -> io::Result<()> {
     let mut file = try!(fs::OpenOptions::new()
         .write(true)
         .truncate(true)
         .create(true)
         .open(path));
 
-    try!(io::Write::write_all(&mut file, contents.as_bytes()));
+    try!(io::Write::write_all(&mut file, contents));
     try!(file.sync_data());
     Ok(())
 }
@@ -0,0 +1,685 @@
+use std::cmp::Ordering;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use git2::{self, AutotagOption, Config as Git2Config, Cred, CredentialType, FetchOptions,
+           ObjectType, Oid, ProxyOptions, RemoteCallbacks, Repository};
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use semver::{Version, VersionReq};
+use tempdir::TempDir;
+use url::Url;
+
+use super::errors::*;
+use super::fsutils;
+use super::location::Revision;
+use super::progress::Reporter;
+
+/// Refspecs to keep branches and tags of bare mirror same as remote.
+const MIRROR_REFSPECS: &'static [&'static str] = &["+refs/heads/*:refs/heads/*",
+                                                   "+refs/tags/*:refs/tags/*"];
+
+/// Environment variable holding access token for HTTPS, unless configured otherwise.
+pub const DEFAULT_TOKEN_ENV: &'static str = "RIG_GIT_TOKEN";
+
+/// How to access remote repositories.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    /// Private key for SSH, tried when ssh-agent fails.
+    pub ssh_key: Option<PathBuf>,
+    /// Name of environment variable holding access token for HTTPS.
+    pub token_env: Option<String>,
+    /// Report progress of fetch and checkout to stderr.
+    pub progress: bool,
+}
+
+impl Settings {
+    fn token(&self) -> Option<String> {
+        let key = self.token_env.as_ref().map(|s| s.as_ref()).unwrap_or(DEFAULT_TOKEN_ENV);
+        env::var(key).ok().and_then(|t| if t.is_empty() { None } else { Some(t) })
+    }
+
+    fn ssh_key(&self) -> Option<PathBuf> {
+        self.ssh_key.clone().or_else(|| {
+            env::home_dir()
+                .map(|home| home.join(".ssh").join("id_rsa"))
+                .and_then(|key| if key.is_file() { Some(key) } else { None })
+        })
+    }
+}
+
+/// Clone remote repository into `dest`, and check out `revision` if any.
+///
+/// Returns id of the commit the working tree is checked out at.
+pub fn clone(url: &Url, revision: Option<&Revision>, dest: &Path, settings: &Settings)
+    -> Result<Oid>
+{
+    let resolved;
+    let revision = match revision {
+        Some(&Revision::Range(ref req)) => {
+            resolved = Revision::Tag(resolve_range(url, req, settings)?);
+            info!("Version `{}` resolved to {}", req, resolved);
+            Some(&resolved)
+        }
+        other => other,
+    };
+
+    let mut builder = RepoBuilder::new();
+    builder.fetch_options(fetch_options(url, settings));
+    if let Some(&Revision::Branch(ref branch)) = revision {
+        builder.branch(branch);
+    }
+    if settings.progress {
+        let mut reporter = Reporter::new(format!("Cloning {}", url));
+        let mut checkout = CheckoutBuilder::new();
+        checkout.progress(move |_, current, total| reporter.files(current, total));
+        builder.with_checkout(checkout);
+    }
+
+    info!("Cloning remote git repository: {:?} into {:?}", url, dest);
+    let repo = builder.clone(url.as_ref(), dest)?;
+
+    match revision {
+        Some(&Revision::Branch(_)) | None => head_commit(&repo),
+        Some(rev) => checkout(&repo, rev),
+    }
+}
+
+/// Fetch only the tip commit of `revision`, and check out files under `subdir` if given.
+/// With `submodules`, submodules under `subdir` are fetched shallowly as well, recursively.
+///
+/// libgit2 can not fetch shallow history, so it runs `git` command installed in the system,
+/// given the same credentials and proxy as other fetches by `CommandAccess`. `url` is expected
+/// to be rewritten by `rewrite_url` already, as the other fetches are.
+pub fn shallow_clone(url: &Url,
+                     revision: Option<&Revision>,
+                     subdir: Option<&str>,
+                     submodules: bool,
+                     dest: &Path,
+                     settings: &Settings)
+                     -> Result<Oid> {
+    let refname = match revision {
+        None => "HEAD".to_string(),
+        Some(&Revision::Branch(ref b)) => format!("refs/heads/{}", b),
+        Some(&Revision::Tag(ref t)) => format!("refs/tags/{}", t),
+        Some(&Revision::Spec(ref r)) => r.clone(),
+        Some(&Revision::Range(ref req)) => {
+            format!("refs/tags/{}", resolve_range(url, req, settings)?)
+        }
+    };
+    let access = CommandAccess::new(url, settings)?;
+
+    info!("Fetching {} of {} into {:?}", refname, url, dest);
+    fs::create_dir_all(dest)?;
+    run_git(dest, &["init", "--quiet"], &access)?;
+    run_git(dest, &["remote", "add", "origin", url.as_str()], &access)?;
+
+    if let Some(subdir) = subdir {
+        debug!("Checking out files under {:?} only", subdir);
+        run_git(dest, &["config", "core.sparseCheckout", "true"], &access)?;
+        let info = dest.join(".git").join("info");
+        fs::create_dir_all(&info)?;
+        fsutils::write_file(&info.join("sparse-checkout"),
+                            &format!("/{}/\n", subdir.trim_matches('/')))?;
+    }
+
+    // blobs outside of sparse checkout are left on server, by git which can filter them
+    let verbosity = if settings.progress { "--progress" } else { "--quiet" };
+    let mut fetch = vec!["fetch", verbosity, "--depth", "1"];
+    if supports_filter(&run_git(dest, &["version"], &access)?) {
+        fetch.push("--filter=blob:none");
+    }
+    fetch.extend(&["origin", &refname]);
+    run_git(dest, &fetch, &access)?;
+    run_git(dest, &["checkout", "--quiet", "FETCH_HEAD"], &access)?;
+
+    if submodules {
+        let mut args = vec!["submodule", "update", verbosity, "--init", "--recursive",
+                            "--depth", "1"];
+        if let Some(subdir) = subdir {
+            args.extend(&["--", subdir]);
+        }
+        run_git(dest, &args, &access)?;
+    }
+
+    let head = run_git(dest, &["rev-parse", "HEAD"], &access)?;
+    Oid::from_str(&head).map_err(|e| e.into())
+}
+
+/// Whether git of `git version` output, like `git version 2.20.1`, supports `--filter` of fetch,
+/// which is since 2.19.
+pub fn supports_filter(version: &str) -> bool {
+    let mut numbers = version.trim()
+        .trim_left_matches("git version ")
+        .split(|c: char| !c.is_digit(10))
+        .map(|n| n.parse::<u64>().ok());
+    match (numbers.next(), numbers.next()) {
+        (Some(Some(major)), Some(Some(minor))) => (major, minor) >= (2, 19),
+        _ => false,
+    }
+}
+
+/// Options and environment of `git` command, so that it accesses remote as libgit2 does
+/// with `fetch_options`: access token through `GIT_ASKPASS`, SSH key through
+/// `GIT_SSH_COMMAND`, and proxy through `http.proxy`.
+#[derive(Debug)]
+pub struct CommandAccess {
+    /// `-c key=value` options given before subcommand.
+    pub configs: Vec<String>,
+    /// Environment variables of git process.
+    pub envs: Vec<(String, String)>,
+    /// Directory of askpass script, which lives as long as this.
+    askpass_dir: Option<TempDir>,
+}
+
+impl CommandAccess {
+    pub fn new(url: &Url, settings: &Settings) -> Result<CommandAccess> {
+        let mut access = CommandAccess {
+            configs: Vec::new(),
+            envs: Vec::new(),
+            askpass_dir: None,
+        };
+
+        if let Some(proxy) = find_proxy_url(url) {
+            access.configs.push(format!("http.proxy={}", proxy));
+        }
+
+        let is_http = url.scheme() == "https" || url.scheme() == "http";
+        if let (true, Some(token)) = (is_http, settings.token()) {
+            // token comes before credential helpers, as in `credentials`
+            let dir = TempDir::new("rig__askpass")?;
+            let script = dir.path().join("askpass.sh");
+            fsutils::write_file(&script, ASKPASS_SCRIPT)?;
+            make_executable(&script)?;
+
+            let username = match url.username() {
+                "" => "x-access-token",
+                name => name,
+            };
+            access.configs.push("credential.helper=".to_string());
+            access.envs.push(("GIT_ASKPASS".into(), script.to_string_lossy().into_owned()));
+            access.envs.push(("RIG_ASKPASS_USERNAME".into(), username.into()));
+            access.envs.push(("RIG_ASKPASS_TOKEN".into(), token));
+            access.askpass_dir = Some(dir);
+        }
+
+        // ssh tries agent first and default keys anyway, so only configured key is added
+        if let Some(ref key) = settings.ssh_key {
+            let command = format!("ssh -i {}", shell_quote(&key.to_string_lossy()));
+            access.envs.push(("GIT_SSH_COMMAND".into(), command));
+        }
+
+        Ok(access)
+    }
+}
+
+/// Answers git's prompts for username and password with what `CommandAccess` gives,
+/// so that the token is never written into a file.
+const ASKPASS_SCRIPT: &'static str = r#"#!/bin/sh
+case "$1" in
+    Username*) echo "$RIG_ASKPASS_USERNAME" ;;
+    *) echo "$RIG_ASKPASS_TOKEN" ;;
+esac
+"#;
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o700)).map_err(|e| e.into())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Quote `s` as one word for `sh`, which runs `GIT_SSH_COMMAND`.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Run git in `dir` with `access`, returning its stdout.
+///
+/// git writes progress to stderr, so it is passed through to ours if `--progress` is given.
+fn run_git(dir: &Path, args: &[&str], access: &CommandAccess) -> Result<String> {
+    debug!("Running git {:?} in {:?}", args, dir);
+    let mut command = Command::new("git");
+    for config in &access.configs {
+        command.arg("-c").arg(config);
+    }
+    command.args(args).current_dir(dir);
+    for &(ref key, ref value) in &access.envs {
+        command.env(key, value);
+    }
+    if args.contains(&"--progress") {
+        command.stderr(Stdio::inherit());
+    }
+    let output = command.output()
+        .map_err(|e| ErrorKind::GitCommand(format!("failed to run git: {}", e)))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = if stderr.trim().is_empty() {
+            output.status.to_string()
+        } else {
+            stderr.trim().to_string()
+        };
+        Err(ErrorKind::GitCommand(format!("git {}: {}", args.join(" "), message)).into())
+    }
+}
+
+/// Create bare mirror of remote repository at `path`, or fetch into it if it exists.
+///
+/// Only objects missing in the mirror are downloaded. A new mirror is fetched into a staging
+/// directory first, so a failed fetch never leaves an empty repository at `path`.
+pub fn mirror(url: &Url, path: &Path, settings: &Settings) -> Result<Repository> {
+    if fsutils::exists(path.join("HEAD")) {
+        let repo = Repository::open(path)?;
+        fetch_mirror(&repo, url, settings)?;
+        return Ok(repo);
+    }
+
+    let staging = PathBuf::from(format!("{}.partial", path.display()));
+    if fsutils::exists(&staging) {
+        fsutils::remove_dir(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    let fetched = Repository::init_bare(&staging)
+        .map_err(Error::from)
+        .and_then(|repo| fetch_mirror(&repo, url, settings));
+    if let Err(e) = fetched {
+        let _ = fsutils::remove_dir(&staging);
+        return Err(e);
+    }
+
+    fs::rename(&staging, path)?;
+    Ok(Repository::open(path)?)
+}
+
+fn fetch_mirror(repo: &Repository, url: &Url, settings: &Settings) -> Result<()> {
+    let mut remote = match repo.find_remote("origin") {
+        Ok(remote) => remote,
+        Err(_) => repo.remote("origin", url.as_ref())?,
+    };
+
+    remote.download(MIRROR_REFSPECS, Some(&mut fetch_options(url, settings)))?;
+    let head = {
+        let heads = remote.list()?;
+        let head = heads.iter().find(|h| h.name() == "HEAD");
+        head.and_then(|h| {
+            h.symref_target()
+                .map(|s| s.to_string())
+                .or_else(|| {
+                    heads.iter()
+                        .find(|b| b.name().starts_with("refs/heads/") && b.oid() == h.oid())
+                        .map(|b| b.name().to_string())
+                })
+        })
+    };
+    remote.disconnect();
+    remote.update_tips(None, false, AutotagOption::Unspecified, None)?;
+
+    if let Some(head) = head {
+        debug!("Mirror HEAD points to {}", head);
+        repo.set_head(&head)?;
+    }
+    Ok(())
+}
+
+/// Check out `revision` into working tree, leaving HEAD detached.
+pub fn checkout(repo: &Repository, revision: &Revision) -> Result<Oid> {
+    let commit = resolve(repo, revision)?;
+    let object = repo.find_object(commit, Some(ObjectType::Commit))?;
+
+    debug!("Checking out {} at {}", revision, commit);
+    repo.checkout_tree(&object, Some(CheckoutBuilder::new().force()))?;
+    repo.set_head_detached(commit)?;
+
+    Ok(commit)
+}
+
+/// Find the commit `revision` points to, in either clone or mirror of remote repository.
+pub fn resolve(repo: &Repository, revision: &Revision) -> Result<Oid> {
+    let candidates = match *revision {
+        Revision::Branch(ref b) => vec![format!("refs/remotes/origin/{}", b),
+                                        format!("refs/heads/{}", b)],
+        Revision::Tag(ref t) => vec![format!("refs/tags/{}", t)],
+        Revision::Spec(ref r) => vec![format!("origin/{}", r), r.clone()],
+        Revision::Range(ref req) => {
+            let tags = repo.tag_names(None)?
+                .iter()
+                .filter_map(|t| t.map(|t| t.to_string()))
+                .collect::<Vec<_>>();
+            let tag = highest_matching(&tags, req)
+                .ok_or(Error::from(ErrorKind::NoMatchingVersion(req.to_string())))?;
+            vec![format!("refs/tags/{}", tag)]
+        }
+    };
+
+    let object = candidates.iter()
+        .filter_map(|spec| repo.revparse_single(spec).ok())
+        .next()
+        .ok_or(Error::from(ErrorKind::RevisionNotFound(revision.to_string())))?;
+    let commit = object.peel(ObjectType::Commit)?;
+    Ok(commit.id())
+}
+
+/// Id of the commit HEAD points to.
+pub fn head_commit(repo: &Repository) -> Result<Oid> {
+    let head = repo.revparse_single("HEAD")?;
+    head.peel(ObjectType::Commit)
+        .map(|commit| commit.id())
+        .map_err(|e| e.into())
+}
+
+/// List names of tags in remote repository, without cloning it.
+pub fn list_tags(url: &Url, settings: &Settings) -> Result<Vec<String>> {
+    // `Remote` needs a repository to live in, though nothing will be fetched into it
+    let scratch = TempDir::new("rig__ls_remote")?;
+    let repo = Repository::init_bare(scratch.path())?;
+    let mut remote = repo.remote_anonymous(url.as_ref())?;
+
+    // git2 does not take proxy options & callbacks on `connect`, so we let `download`
+    // connect instead, with a refspec matching nothing. It leaves the connection open.
+    remote.download(&["refs/rig/ls-remote:refs/rig/ls-remote"],
+                  Some(&mut fetch_options(url, settings)))?;
+    let mut tags = remote.list()?
+        .iter()
+        .filter(|head| !head.name().ends_with("^{}"))
+        .filter_map(|head| {
+            if head.name().starts_with("refs/tags/") {
+                Some(head.name()["refs/tags/".len()..].to_string())
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    remote.disconnect();
+
+    tags.sort_by(|a, b| match (tag_version(a), tag_version(b)) {
+        (Some(va), Some(vb)) => va.cmp(&vb),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    });
+    Ok(tags)
+}
+
+/// Parse tag name like `v1.2.3` or `1.2.3` as semantic version.
+pub fn tag_version(tag: &str) -> Option<Version> {
+    let version = if tag.starts_with('v') || tag.starts_with('V') {
+        &tag[1..]
+    } else {
+        tag
+    };
+    Version::parse(version).ok()
+}
+
+/// Find the highest tag in remote repository which satisfies `req`.
+pub fn resolve_range(url: &Url, req: &VersionReq, settings: &Settings) -> Result<String> {
+    let tags = list_tags(url, settings)?;
+    highest_matching(&tags, req)
+        .ok_or(ErrorKind::NoMatchingVersion(req.to_string()).into())
+}
+
+/// Pick the highest tag which satisfies `req` out of `tags`.
+pub fn highest_matching(tags: &[String], req: &VersionReq) -> Option<String> {
+    tags.iter()
+        .filter_map(|tag| tag_version(tag).map(|v| (v, tag)))
+        .filter(|&(ref v, _)| req.matches(v))
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, tag)| tag.clone())
+}
+
+fn fetch_options<'a>(url: &Url, settings: &'a Settings) -> FetchOptions<'a> {
+    let mut fetch = FetchOptions::new();
+    fetch.remote_callbacks(remote_callbacks(url, settings));
+
+    if let Some(proxy_url) = find_proxy_url(url) {
+        debug!("Proxy settings found, initializing fetch options.");
+
+        let mut proxy = ProxyOptions::new();
+        proxy.url(proxy_url.as_ref());
+        fetch.proxy_options(proxy);
+    } else {
+        debug!("No proxy settings found.")
+    }
+
+    fetch
+}
+
+fn remote_callbacks<'a>(url: &Url, settings: &'a Settings) -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut tried = Attempts::default();
+    callbacks.credentials(move |url, username, allowed| {
+        credentials(settings, &mut tried, url, username, allowed)
+    });
+
+    if settings.progress {
+        let mut reporter = Reporter::new(format!("Fetching {}", url));
+        callbacks.transfer_progress(move |stats| {
+            reporter.transfer(&stats);
+            true
+        });
+    }
+    callbacks
+}
+
+/// Credentials already offered to remote, as git2 keeps asking until we give up.
+#[derive(Debug, Default)]
+struct Attempts {
+    username: bool,
+    ssh_agent: bool,
+    ssh_key: bool,
+    token: bool,
+    helper: bool,
+}
+
+fn credentials(settings: &Settings,
+               tried: &mut Attempts,
+               url: &str,
+               username: Option<&str>,
+               allowed: CredentialType)
+               -> ::std::result::Result<Cred, git2::Error> {
+
+    let ssh_user = username.unwrap_or("git");
+
+    if allowed.contains(git2::USERNAME) && !tried.username {
+        tried.username = true;
+        return Cred::username(ssh_user);
+    }
+
+    if allowed.contains(git2::SSH_KEY) {
+        if !tried.ssh_agent {
+            tried.ssh_agent = true;
+            debug!("Authenticating {} with ssh-agent", url);
+            return Cred::ssh_key_from_agent(ssh_user);
+        }
+        if !tried.ssh_key {
+            tried.ssh_key = true;
+            if let Some(key) = settings.ssh_key() {
+                debug!("Authenticating {} with key {:?}", url, key);
+                return Cred::ssh_key(ssh_user, None, &key, None);
+            }
+        }
+    }
+
+    if allowed.contains(git2::USER_PASS_PLAINTEXT) {
+        if !tried.token {
+            tried.token = true;
+            if let Some(token) = settings.token() {
+                debug!("Authenticating {} with access token", url);
+                return Cred::userpass_plaintext(username.unwrap_or("x-access-token"), &token);
+            }
+        }
+        if !tried.helper {
+            tried.helper = true;
+            if let Ok(config) = Git2Config::open_default() {
+                debug!("Authenticating {} with git credential helper", url);
+                return Cred::credential_helper(&config, url, username);
+            }
+        }
+    }
+
+    Err(git2::Error::from_str(&format!("No more credentials to try for {}", url)))
+}
+
+/// Proxy to connect `url` through, following curl's conventions.
+///
+/// Hosts in `no_proxy` are connected directly. Otherwise `https_proxy` (or `http_proxy` for plain
+/// http), `all_proxy` and git's `http.proxy` are looked up in this order.
+pub fn find_proxy_url(url: &Url) -> Option<Url> {
+    let host = match url.host_str() {
+        Some(host) if url.scheme() == "https" || url.scheme() == "http" => host,
+        _ => return None,
+    };
+
+    if let Some(no_proxy) = env_value(&["no_proxy", "NO_PROXY"]) {
+        if bypass_proxy(host, &no_proxy) {
+            debug!("{} matches `no_proxy`, connecting directly", host);
+            return None;
+        }
+    }
+
+    let keys: &[&str] = if url.scheme() == "https" {
+        &["https_proxy", "HTTPS_PROXY", "all_proxy", "ALL_PROXY"]
+    } else {
+        &["http_proxy", "all_proxy", "ALL_PROXY"]
+    };
+
+    // we take env vars first
+    for key in keys {
+        if let Some(value) = env_value(&[key]) {
+            match parse_proxy(&value) {
+                Some(proxy) => {
+                    debug!("Setting proxy configuration from environment key: `{}`.", key);
+                    return Some(proxy);
+                }
+                None => warn!("Ignoring invalid proxy URL in `{}`: {}", key, value),
+            }
+        }
+    }
+
+    // if no env vars set, look for git config
+    match Git2Config::open_default() {
+        Ok(config) => {
+            config.get_string("http.proxy").ok().and_then(|value| {
+                let proxy = parse_proxy(&value);
+                if proxy.is_none() {
+                    warn!("Ignoring invalid proxy URL in git config `http.proxy`: {}", value);
+                }
+                proxy
+            })
+        }
+        Err(_) => {
+            warn!("Cannot locate or open git global configuration");
+            None
+        }
+    }
+}
+
+/// User's name and email in git configuration.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Identity {
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+/// Read `user.name` and `user.email` from git configuration.
+pub fn identity() -> Identity {
+    match Git2Config::open_default() {
+        Ok(config) => {
+            Identity {
+                name: config.get_string("user.name").ok(),
+                email: config.get_string("user.email").ok(),
+            }
+        }
+        Err(_) => Identity::default(),
+    }
+}
+
+/// Whether `host` is listed in `no_proxy`, as itself or one of its parent domains.
+pub fn bypass_proxy(host: &str, no_proxy: &str) -> bool {
+    let host = host.to_lowercase();
+    no_proxy.split(|c| c == ',' || c == ' ')
+        .map(|pattern| pattern.trim().to_lowercase())
+        .filter(|pattern| !pattern.is_empty())
+        .any(|pattern| {
+            if pattern == "*" {
+                return true;
+            }
+            let domain = pattern.trim_left_matches('*').trim_left_matches('.');
+            let domain = domain.split(':').next().unwrap_or(domain);
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+}
+
+/// Parse proxy URL, which may lack its scheme like `proxy.example.com:8080`.
+pub fn parse_proxy(raw: &str) -> Option<Url> {
+    let raw = raw.trim();
+    let parsed = if raw.contains("://") {
+        Url::parse(raw)
+    } else {
+        Url::parse(&format!("http://{}", raw))
+    };
+    parsed.ok().and_then(|url| if url.host_str().is_some() { Some(url) } else { None })
+}
+
+fn env_value(keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|key| env::var(key).ok())
+        .find(|value| !value.trim().is_empty())
+}
+
+/// `url.<base>.insteadOf` rules in git configuration, as pairs of prefix and replacement.
+pub fn url_rewrites() -> Vec<(String, String)> {
+    let config = match Git2Config::open_default() {
+        Ok(config) => config,
+        Err(_) => return Vec::new(),
+    };
+    let entries = match config.entries(Some(r"^url\..*\.insteadof$")) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut rewrites = Vec::new();
+    for entry in &entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+        if let (Some(name), Some(prefix)) = (entry.name(), entry.value()) {
+            let base = &name["url.".len()..name.len() - ".insteadof".len()];
+            rewrites.push((prefix.to_string(), base.to_string()));
+        }
+    }
+    rewrites
+}
+
+/// Apply the rule with the longest matching prefix to `url`, as git does.
+pub fn rewrite_url(url: &Url, rewrites: &[(String, String)]) -> Url {
+    let raw = url.as_str();
+    let rule = rewrites.iter()
+        .filter(|&&(ref prefix, _)| raw.starts_with(prefix.as_str()))
+        .max_by_key(|&&(ref prefix, _)| prefix.len());
+
+    match rule {
+        Some(&(ref prefix, ref base)) => {
+            let rewritten = format!("{}{}", base, &raw[prefix.len()..]);
+            match Url::parse(&rewritten) {
+                Ok(rewritten) => {
+                    info!("Rewriting {} to {} (url.{}.insteadOf)", url, rewritten, base);
+                    rewritten
+                }
+                Err(e) => {
+                    warn!("Ignoring rewrite of {} to invalid URL {}: {}", url, rewritten, e);
+                    url.clone()
+                }
+            }
+        }
+        None => url.clone(),
+    }
+}
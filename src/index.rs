@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use serde_json;
+use serde_json::value::Value;
+use url::Url;
+
+use super::archive;
+use super::errors::*;
+use super::fsutils;
+
+/// Catalog of templates, read from JSON file on local file system or HTTP(S) server.
+///
+/// ```json
+/// [
+///   {
+///     "name": "svc",
+///     "location": "gl:platform/service-template#v3",
+///     "description": "Microservice with CI pipeline",
+///     "tags": ["rust", "docker"]
+///   }
+/// ]
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Index {
+    pub entries: Vec<Entry>,
+}
+
+/// A template listed in index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub location: String,
+    pub description: String,
+    pub tags: Vec<String>,
+}
+
+impl Index {
+    /// Read index from path or URL.
+    pub fn load(location: &str) -> Result<Index> {
+        let content = if location.starts_with("http://") || location.starts_with("https://") ||
+                         location.starts_with("file://") {
+            let bytes = archive::fetch(&Url::parse(location)?)?;
+            String::from_utf8(bytes)
+                .map_err(|_| Error::from(ErrorKind::InvalidIndex(location.to_string())))?
+        } else {
+            fsutils::read_file(Path::new(location))?
+        };
+        Index::from_json(&content).chain_err(|| ErrorKind::InvalidIndex(location.to_string()))
+    }
+
+    pub fn from_json(content: &str) -> Result<Index> {
+        let value: Value = serde_json::from_str(content)?;
+        let items = value.as_array()
+            .ok_or(Error::from("index must be an array of templates"))?;
+
+        let mut entries = Vec::new();
+        for item in items {
+            let field = |key: &str| item.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+            let (name, location) = match (field("name"), field("location")) {
+                (Some(name), Some(location)) => (name, location),
+                _ => return Err("every template needs `name` and `location`".into()),
+            };
+            let tags = item.get("tags")
+                .and_then(|v| v.as_array())
+                .map(|tags| tags.iter().filter_map(|t| t.as_str()).map(|t| t.to_string()).collect())
+                .unwrap_or(Vec::new());
+
+            entries.push(Entry {
+                name: name,
+                location: location,
+                description: field("description").unwrap_or(String::new()),
+                tags: tags,
+            });
+        }
+        Ok(Index { entries: entries })
+    }
+
+    /// Templates whose name, location, description or tags contain `term`, ignoring case.
+    pub fn search(&self, term: &str) -> Vec<&Entry> {
+        let term = term.to_lowercase();
+        self.entries
+            .iter()
+            .filter(|entry| {
+                entry.name.to_lowercase().contains(&term) ||
+                entry.location.to_lowercase().contains(&term) ||
+                entry.description.to_lowercase().contains(&term) ||
+                entry.tags.iter().any(|tag| tag.to_lowercase().contains(&term))
+            })
+            .collect()
+    }
+}
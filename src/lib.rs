@@ -1,28 +1,51 @@
 #![recursion_limit = "1024"]
 
+extern crate atty;
 extern crate combine;
+extern crate curl;
 extern crate env_logger;
 #[macro_use]
 extern crate error_chain;
+extern crate flate2;
 extern crate git2;
 extern crate java_properties;
 #[macro_use]
+extern crate lazy_static;
+#[macro_use]
 extern crate log;
 extern crate rand;
+extern crate regex;
 extern crate rustc_serialize;
+extern crate semver;
 extern crate serde;
 extern crate serde_json;
+extern crate tar;
 extern crate tempdir;
 #[macro_use]
 extern crate tera;
 extern crate toml;
 extern crate url;
 extern crate walkdir;
+extern crate xz2;
+extern crate zip;
 
+pub mod answers;
+pub mod archive;
+pub mod builtins;
+pub mod cache;
+pub mod config;
 pub mod errors;
 pub mod filters;
 pub mod format;
 pub mod fsutils;
+pub mod git;
+pub mod index;
+pub mod location;
+pub mod maven;
+pub mod overrides;
 pub mod parser;
+pub mod progress;
 pub mod project;
+pub mod schema;
+pub mod source;
 pub mod template;
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use semver::VersionReq;
+use url::Url;
+
+use super::archive::Format;
+use super::errors::*;
+use super::fsutils;
+
+/// Where the template lives, resolved from `<repository>` argument.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    /// A directory on local file system, used as is without cloning.
+    Local(PathBuf),
+    /// A remote git repository which must be cloned before generation,
+    /// optionally pinned to a revision.
+    Remote(Url, Option<Revision>),
+    /// A `.tar.gz`, `.tar.xz` or `.zip` archive, as `file://` or HTTP(S) URL.
+    Archive(Url),
+}
+
+/// Revision of template repository to generate project from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Revision {
+    Branch(String),
+    Tag(String),
+    /// Anything git can resolve to a commit: commit id, branch or tag name.
+    Spec(String),
+    /// The highest tag satisfying the semver requirement.
+    Range(VersionReq),
+}
+
+impl fmt::Display for Revision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Revision::Branch(ref b) => write!(f, "branch `{}`", b),
+            Revision::Tag(ref t) => write!(f, "tag `{}`", t),
+            Revision::Spec(ref r) => write!(f, "revision `{}`", r),
+            Revision::Range(ref req) => write!(f, "version `{}`", req),
+        }
+    }
+}
+
+impl Location {
+    /// Resolve the `<repository>` argument.
+    ///
+    /// It accepts `file://` URL, path to an existing directory (absolute or relative),
+    /// URL of remote git repository, `prefix:owner/project` shorthand for one of the `hosts`,
+    /// or `owner/project` shorthand for the default host (GitHub unless configured).
+    /// An existing local directory takes precedence over the shorthand.
+    ///
+    /// Paths and URLs of archives are recognized by extension: `.tar.gz`, `.tgz`,
+    /// `.tar.xz`, `.txz` and `.zip`.
+    ///
+    /// Remote locations can be suffixed with `#ref` to pin branch, tag or commit,
+    /// like `owner/project#v1.0`, or with `@range` to pick the highest tag matching
+    /// semver requirement, like `owner/project@^1.2`.
+    pub fn parse(raw: &str, hosts: &Hosts) -> Result<Location> {
+        if let Some(location) = archive(raw)? {
+            return Ok(location);
+        }
+
+        if raw.starts_with("file://") {
+            let url = Url::parse(raw)?;
+            return url.to_file_path()
+                .map_err(|_| ErrorKind::InvalidUrlFormat(raw.to_string()).into())
+                .and_then(|path| local_dir(&path));
+        }
+
+        if fsutils::is_directory(raw) || is_path_like(raw) {
+            return local_dir(Path::new(raw));
+        }
+
+        let (repo, revision) = split_revision(raw)?;
+        hosts.expand(repo).map(|url| Location::Remote(url, revision))
+    }
+
+    /// Pin remote location to the given revision, replacing the one from `#ref` shorthand.
+    pub fn with_revision(self, revision: Option<Revision>) -> Location {
+        match self {
+            Location::Remote(url, shorthand) => Location::Remote(url, revision.or(shorthand)),
+            local => local,
+        }
+    }
+}
+
+/// Split `#ref` or `@range` suffix from repository.
+fn split_revision(raw: &str) -> Result<(&str, Option<Revision>)> {
+    if let Some(i) = raw.find('#') {
+        let (repo, spec) = (&raw[..i], &raw[i + 1..]);
+        if spec.is_empty() {
+            return Err(ErrorKind::InvalidUrlFormat(raw.to_string()).into());
+        }
+        return Ok((repo, Some(Revision::Spec(spec.to_string()))));
+    }
+
+    // `@` may also appear in user info of URL, so we only look into the last segment
+    let last_segment = raw.rfind('/').map(|i| i + 1).unwrap_or(0);
+    if let Some(i) = raw[last_segment..].find('@') {
+        let (repo, range) = raw.split_at(last_segment + i);
+        let req = VersionReq::parse(&range[1..])?;
+        return Ok((repo, Some(Revision::Range(req))));
+    }
+
+    Ok((raw, None))
+}
+
+fn archive(raw: &str) -> Result<Option<Location>> {
+    if ["file://", "http://", "https://"].iter().any(|scheme| raw.starts_with(scheme)) {
+        let url = Url::parse(raw)?;
+        if Format::from_name(url.path()).is_none() {
+            return Ok(None);
+        }
+        if url.scheme() == "file" {
+            let path = url.to_file_path()
+                .map_err(|_| Error::from(ErrorKind::InvalidUrlFormat(raw.to_string())))?;
+            if !path.is_file() {
+                return Err(ErrorKind::TemplateNotFound(raw.to_string()).into());
+            }
+        }
+        return Ok(Some(Location::Archive(url)));
+    }
+
+    if Format::from_name(raw).is_none() || !(fsutils::exists(raw) || is_path_like(raw)) {
+        return Ok(None);
+    }
+    let path = Path::new(raw);
+    if !path.is_file() {
+        return Err(ErrorKind::TemplateNotFound(raw.to_string()).into());
+    }
+    let abs = env::current_dir()?.join(path);
+    Url::from_file_path(&abs)
+        .map(|url| Some(Location::Archive(url)))
+        .map_err(|_| ErrorKind::InvalidUrlFormat(raw.to_string()).into())
+}
+
+fn is_path_like(raw: &str) -> bool {
+    raw.starts_with('.') || Path::new(raw).is_absolute()
+}
+
+fn local_dir(path: &Path) -> Result<Location> {
+    if fsutils::is_directory(path) {
+        let abs = if path.is_relative() {
+            env::current_dir()?.join(path)
+        } else {
+            path.to_path_buf()
+        };
+        Ok(Location::Local(abs))
+    } else {
+        Err(ErrorKind::TemplateNotFound(path.to_string_lossy().into_owned()).into())
+    }
+}
+
+/// URL schemes git can clone from.
+const SUPPORTED_SCHEMES: &'static [&'static str] = &["https", "http", "ssh", "git"];
+
+/// Git hosting services which can be referred by shorthand prefix, like `gl:owner/project`.
+#[derive(Clone, Debug)]
+pub struct Hosts {
+    bases: HashMap<String, String>,
+    default: String,
+}
+
+impl Default for Hosts {
+    fn default() -> Hosts {
+        let mut hosts = Hosts {
+            bases: HashMap::new(),
+            default: "gh".into(),
+        };
+        hosts.insert("gh", "https://github.com");
+        hosts.insert("gl", "https://gitlab.com");
+        hosts.insert("bb", "https://bitbucket.org");
+        hosts
+    }
+}
+
+impl Hosts {
+    /// Register base URL of git host under `prefix`, replacing the existing one if any.
+    pub fn insert(&mut self, prefix: &str, base: &str) {
+        self.bases.insert(prefix.into(), base.trim_right_matches('/').into());
+    }
+
+    /// Use the host of `prefix` for `owner/project` shorthand without prefix.
+    pub fn set_default(&mut self, prefix: &str) -> Result<()> {
+        if self.bases.contains_key(prefix) {
+            self.default = prefix.into();
+            Ok(())
+        } else {
+            Err(ErrorKind::UnknownHost(prefix.to_string()).into())
+        }
+    }
+
+    pub fn base(&self, prefix: &str) -> Option<&str> {
+        self.bases.get(prefix).map(|s| s.as_ref())
+    }
+
+    /// Expand shorthand into URL of repository, or parse it as URL.
+    ///
+    /// scp-like syntax for SSH, like `git@github.com:owner/project`, is converted into `ssh://` URL.
+    pub fn expand(&self, raw: &str) -> Result<Url> {
+        if let Some(i) = raw.find(':') {
+            let (prefix, path) = (&raw[..i], &raw[i + 1..]);
+            if let Some(base) = self.base(prefix) {
+                return join_url(base, path, raw);
+            }
+            if prefix.contains('@') && !path.starts_with("//") {
+                return join_url(&format!("ssh://{}", prefix), path, raw);
+            }
+            if !path.starts_with("//") {
+                return Err(ErrorKind::UnknownHost(prefix.to_string()).into());
+            }
+
+            let url = Url::parse(raw)?;
+            if SUPPORTED_SCHEMES.contains(&url.scheme()) {
+                Ok(url)
+            } else {
+                Err(ErrorKind::InvalidUrlFormat(raw.to_string()).into())
+            }
+        } else {
+            join_url(self.base(&self.default).unwrap(), raw, raw)
+        }
+    }
+}
+
+fn join_url(base: &str, path: &str, raw: &str) -> Result<Url> {
+    let path = path.trim_matches('/');
+    if path.split('/').filter(|s| !s.is_empty()).count() < 2 {
+        return Err(ErrorKind::InvalidUrlFormat(raw.to_string()).into());
+    }
+    Url::parse(&format!("{}/{}", base, path)).map_err(|e| ErrorKind::ParseUrl(e).into())
+}
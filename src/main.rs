@@ -1,3 +1,4 @@
+extern crate atty;
 extern crate docopt;
 extern crate error_chain;
 extern crate env_logger;
@@ -7,36 +8,48 @@ extern crate java_properties;
 extern crate log;
 extern crate rustc_serialize;
 extern crate tempdir;
+extern crate toml;
 extern crate url;
 
 extern crate rig;
 
-use std::collections::HashMap;
 use std::env;
+use std::fmt;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::process::exit;
 
 use docopt::Docopt;
-use git2::{Config as Git2Config, FetchOptions};
-use git2::build::RepoBuilder;
 use tempdir::TempDir;
+use toml::value::{Table, Value};
 use url::Url;
 
-use rig::errors::*;
+use rig::answers;
+use rig::archive;
+use rig::builtins::Builtins;
+use rig::config::{self, UserConfig};
 use rig::format::{format, Formatter};
+use rig::fsutils;
+use rig::git;
+use rig::index::Index;
+use rig::location::{Location, Revision};
+use rig::maven;
+use rig::overrides;
 use rig::project::{Configuration, Project};
-use rig::template::Params;
+use rig::schema::ParamSpec;
+use rig::source::{DirSource, GitSource, Source};
+use rig::template::{self, Params};
 
 const USAGE: &'static str = r#"
 Rig - Generate new project by cloning templates from git repository.
 
-*NOTE* This software is under early development, it lacks features such:
-  - Currently it can only use templates that hosted on GitHub
-  - giter8 compatibility features (e.g. maven directive) are not yet supported.
-
 Usage:
-    rig <repository> [options]
+    rig <repository> [options] [--param PARAM]...
+    rig versions <repository>
+    rig search <term>
+    rig cache list
+    rig cache update [<repository>]
+    rig cache clean [<repository>]
     rig (-h | --help)
     rig (-V | --version)
 
@@ -44,8 +57,20 @@ Options:
     -h, --help              Show help message
     -V, --version           Show version
     --name NAME             Specify project name (overrides default if any)
+    -P, --param PARAM       Set parameter as `key=value`, without being asked (repeatable).
+                            giter8 style `--key=value` works as well
+    --answers FILE          Read parameters from TOML or JSON file, like `.rig-answers.toml`
+                            saved in generated project
     --output PATH           Specify output directory to generate project
     --root PATH             Specify directory where template lives in repository
+    --branch NAME           Generate from the branch of template repository
+    --tag NAME              Generate from the tag of template repository
+    --rev COMMIT            Generate from the commit of template repository
+    --offline               Generate from cached template, without accessing network
+    --shallow               Fetch only the tip commit and files under template root,
+                            bypassing cache (requires `git` command)
+    --no-submodules         Leave submodules of template repository empty
+    -q, --quiet             Do not report progress of fetching template and writing files
     --verbatim EXTENSION    Comma separeted list of files exclude from template processing
     -p, --packaged          Force format `package` parameter value into directory tree
     -Y, --confirm           Use template default value to all parameters (Yes-To-All)
@@ -55,10 +80,26 @@ Options:
 
 #[derive(Debug, RustcDecodable)]
 struct Args {
+    cmd_versions: bool,
+    cmd_search: bool,
+    cmd_cache: bool,
+    cmd_list: bool,
+    cmd_update: bool,
+    cmd_clean: bool,
     arg_repository: String,
+    arg_term: String,
     flag_name: Option<String>,
+    flag_param: Vec<String>,
+    flag_answers: Option<String>,
     flag_output: Option<String>,
     flag_root: Option<String>,
+    flag_branch: Option<String>,
+    flag_tag: Option<String>,
+    flag_rev: Option<String>,
+    flag_offline: bool,
+    flag_shallow: bool,
+    flag_no_submodules: bool,
+    flag_quiet: bool,
     flag_verbatim: Option<String>, // unimplemented!
     flag_packaged: bool,
     flag_confirm: bool,
@@ -73,8 +114,10 @@ fn main() {
 
     env_logger::init().unwrap();
 
+    // `--key=value` arguments are not options docopt knows
+    let (argv, arg_params) = overrides::split_args(env::args(), USAGE);
     let args: Args = Docopt::new(USAGE)
-        .and_then(|d| d.decode())
+        .and_then(|d| d.argv(argv).decode())
         .unwrap_or_else(|e| e.exit());
 
     debug!("{:?}", args);
@@ -84,116 +127,405 @@ fn main() {
         exit(0);
     }
 
-    // gather info of remote repository & networks
-    let url = normalize_url(&args.arg_repository).unwrap();
-    let mut repo = RepoBuilder::new();
-    if let Some(proxy_url) = find_proxy_url() {
-
-        debug!("Proxy settings found, initializing fetch options.");
+    let mut config = UserConfig::load().unwrap_or_else(|e| {
+        warn!("Failed to read user configuration, using defaults: {}", e);
+        UserConfig::default()
+    });
+    config.git.progress = !args.flag_quiet;
 
-        let mut proxy = git2::ProxyOptions::new();
-        proxy.url(proxy_url.as_ref());
+    if args.cmd_versions {
+        print_versions(&args.arg_repository, &config);
+        exit(0);
+    }
 
-        let mut fetch = FetchOptions::new();
-        fetch.proxy_options(proxy);
+    if args.cmd_search {
+        search_templates(&args.arg_term, &config);
+        exit(0);
+    }
 
-        repo.fetch_options(fetch);
-    } else {
-        debug!("No proxy settings found.")
+    if args.cmd_cache {
+        run_cache_command(&args, &config);
+        exit(0);
     }
 
-    let clone_root = TempDir::new("rig__template").expect("Failed to create temporal directory");
-    info!("Cloning remote git repository: {:?} into {:?}",
-          url,
-          clone_root.path());
-    let _ = repo.clone(url.as_ref(), &clone_root.path()).unwrap();
+    let revision = match (args.flag_branch.clone(), args.flag_tag.clone(), args.flag_rev.clone()) {
+        (Some(b), None, None) => Some(Revision::Branch(b)),
+        (None, Some(t), None) => Some(Revision::Tag(t)),
+        (None, None, Some(r)) => Some(Revision::Spec(r)),
+        (None, None, None) => None,
+        _ => fail("Only one of `--branch`, `--tag` or `--rev` can be specified"),
+    };
+
+    if args.flag_offline && args.flag_shallow {
+        fail("`--offline` and `--shallow` can not be used together");
+    }
 
-    let project = if args.flag_giter8 {
+    let mut project = if args.flag_giter8 {
         Project::new_g8(Some("src/main/g8"))
     } else {
         Project::new(args.flag_root.as_ref(),
                      Configuration::Toml, // TODO: parameterize config format
                      args.flag_packaged)
     };
+    project.progress = !args.flag_quiet;
+
+    // local templates are used in place, remote ones are read from cache without checking out,
+    // unless fetched shallowly into temporal directory
+    let location = parse_location(&args.arg_repository, &config).with_revision(revision);
+    let template_url = match location {
+        Location::Local(ref path) => path.to_string_lossy().into_owned(),
+        Location::Remote(ref url, _) |
+        Location::Archive(ref url) => url.to_string(),
+    };
+    let (source, clone_root, commit): (Box<Source>, _, _) = match location {
+        Location::Local(path) => {
+            info!("Using local template directory: {:?}", path);
+            (Box::new(DirSource::new(path)), None, None)
+        }
+        Location::Archive(url) => {
+            if args.flag_offline && url.scheme() != "file" {
+                fail("Archives on HTTP server can not be used with `--offline`");
+            }
+            (Box::new(archive::open(&url).unwrap_or_else(|e| fail(e))), None, None)
+        }
+        Location::Remote(url, revision) => {
+            if args.flag_shallow {
+                let clone_root = TempDir::new("rig__template")
+                    .expect("Failed to create temporal directory");
+                let subdir = project.root_path.as_ref().map(|s| s.as_ref());
+                let commit = git::shallow_clone(&url,
+                                                revision.as_ref(),
+                                                subdir,
+                                                !args.flag_no_submodules,
+                                                clone_root.path(),
+                                                &config.git)
+                    .unwrap_or_else(|e| fail(e));
+                (Box::new(DirSource::new(clone_root.path())), Some(clone_root), Some(commit))
+            } else {
+                let cached = fetch_cache(&url, &config, args.flag_offline);
+                let mut source = GitSource::open(&cached, revision.as_ref())
+                    .unwrap_or_else(|e| fail(e));
+                if !args.flag_no_submodules {
+                    let rewrites = git::url_rewrites();
+                    let fetch = |url: &Url| {
+                        let url = git::rewrite_url(url, &rewrites);
+                        Ok(fetch_cache(&url, &config, args.flag_offline))
+                    };
+                    if let Err(e) = source.load_submodules(&url, &fetch) {
+                        fail(e);
+                    }
+                }
+                let commit = source.commit();
+                (Box::new(source), None, Some(commit))
+            }
+        }
+    };
 
-    let mut params = project
-        .default_params(&clone_root.path())
-        .unwrap_or(Params::minimal_req());
+    // a template may come without defaults file, but a broken one is an error
+    let defaults_file = project.template_dir(&*source).join(project.config_name());
+    let mut params = if source.is_file(&defaults_file) {
+        project.default_params_from(&*source).unwrap_or_else(|e| fail(e))
+    } else {
+        Params::minimal_req()
+    };
     debug!("Read default context: {:?}", params);
 
-    if !args.flag_confirm {
-        collect_params(&args.flag_name, &mut params.param_map);
+    // user's own defaults come before template ones
+    let prefilled = overrides::prefill(&mut params, &config.profile(&git::identity()));
+
+    // values from answers file, environment, then command line, are not asked
+    let mut given = Vec::new();
+    if let Some(ref path) = args.flag_answers {
+        let answers = answers::read(Path::new(path))
+            .and_then(|answers| overrides::apply_values(&mut params, answers));
+        match answers {
+            Ok(keys) => given.extend(keys),
+            Err(e) => fail(format!("Failed to read answers from {}: {}", path, e)),
+        }
+    }
+
+    let mut pairs = overrides::env_pairs(env::vars(), &params.keys);
+    for raw in &args.flag_param {
+        match overrides::parse_pair(raw) {
+            Some(pair) => pairs.push(pair),
+            None => fail(format!("`--param` must be given as `key=value`: {}", raw)),
+        }
+    }
+    pairs.extend(arg_params);
+    pairs.extend(args.flag_name.iter().map(|name| ("name".to_string(), name.clone())));
+    given.extend(overrides::apply(&mut params, pairs).unwrap_or_else(|e| fail(e)));
+
+    // versions are looked up only for parameters nobody has given
+    if let Configuration::JavaProps = project.config {
+        config.maven.offline = args.flag_offline;
+        let answered = given.iter().chain(&prefilled).cloned().collect::<Vec<_>>();
+        maven::resolve_params(&mut params, &config.maven, &answered);
+    }
+
+    // nobody can answer prompts in scripts and CI, nor when `--confirm` skips them,
+    // so defaults are used as long as every parameter has one
+    let prompting = atty::is(atty::Stream::Stdin) && !args.flag_confirm;
+    if !prompting {
+        let answered = given.iter().chain(&prefilled).cloned().collect::<Vec<_>>();
+        let missing = overrides::missing(&params, &answered).unwrap_or_else(|e| fail(e));
+        if !missing.is_empty() {
+            fail(format!("Missing answers for parameters without default: {}",
+                         missing.join(", ")));
+        }
+    }
+
+    // built-in variables are set again once `name`, which output directory depends on, is fixed
+    let builtins = |params: &Params| {
+        let output_dir = get_output_dir(&args.flag_output, params.get("name").unwrap());
+        Builtins::new(&output_dir, &template_url, commit.map(|c| c.to_string())).to_table()
+    };
+    let initial = builtins(&params);
+    params.set_builtins(initial);
+
+    if prompting {
+        collect_params(&given, &mut params, &builtins);
         debug!("Context updated with user input: {:?}", params);
     }
+    if let Err(e) = params.compute("name") {
+        fail(e);
+    }
 
     // ensure we have real path to output directory
     let output_dir = get_output_dir(&args.flag_output, params.get("name").unwrap());
     debug!("Set output directory: {:?}", output_dir);
+    let fixed = builtins(&params);
+    params.set_builtins(fixed);
 
-    project.generate(&params, &clone_root.path(), &output_dir, args.flag_dry_run).unwrap();
+    if let Err(e) = params.compute_all() {
+        fail(e);
+    }
+
+    if let Err(e) = project.generate_from(&params, &*source, &output_dir, args.flag_dry_run) {
+        fail(e);
+    }
+    if !args.flag_dry_run {
+        let saved = answers::to_toml(&params).and_then(|content| {
+            Ok(fsutils::write_file(&output_dir.join(answers::FILE_NAME), &content)?)
+        });
+        if let Err(e) = saved {
+            fail(format!("Failed to save answers: {}", e));
+        }
+    }
 
     println!("Project successfully generated: {:?}", &output_dir);
+    if let Some(commit) = commit {
+        println!("Template revision: {}", commit);
+    }
     drop(clone_root);
 }
 
-fn find_proxy_url() -> Option<Url> {
+/// Report `message` to stderr, apart from normal output, and exit with failure.
+fn fail<D: fmt::Display>(message: D) -> ! {
+    let _ = writeln!(io::stderr(), "{}", message);
+    exit(1);
+}
 
-    // we take env vars first
-    if let Some(env_val) = env::var_os("http_proxy") {
-        debug!("Setting proxy configuration from environment key: `http_proxy`.");
-        Url::parse(&env_val.to_string_lossy()).ok()
+/// Parse repository argument, which may be an alias in user configuration,
+/// applying `url.<base>.insteadOf` rules of git configuration.
+fn parse_location(raw: &str, config: &UserConfig) -> Location {
+    match Location::parse(config.expand_alias(raw), &config.hosts).unwrap_or_else(|e| fail(e)) {
+        Location::Remote(url, revision) => {
+            Location::Remote(git::rewrite_url(&url, &git::url_rewrites()), revision)
+        }
+        local => local,
+    }
+}
+
+fn fetch_cache(url: &Url, config: &UserConfig, offline: bool) -> PathBuf {
+    let cache = config.cache();
+    if offline {
+        return cache.get(url).unwrap_or_else(|e| fail(e));
+    }
+
+    match cache.update(url, &config.git) {
+        Ok(path) => path,
+        Err(ref e) if cache.contains(url) => {
+            warn!("Failed to update cache, using cached template as is: {}", e);
+            cache.path_for(url)
+        }
+        Err(e) => fail(e),
+    }
+}
+
+fn run_cache_command(args: &Args, config: &UserConfig) {
+    let cache = config.cache();
+    let target = if args.arg_repository.is_empty() {
+        None
     } else {
-        // if no env vars set, look for git global config
-        if let Ok(global_conf) = Git2Config::find_global() {
+        match parse_location(&args.arg_repository, config) {
+            Location::Remote(url, _) => Some(url),
+            Location::Local(path) => fail(format!("Local templates are not cached: {:?}", path)),
+            Location::Archive(url) => fail(format!("Archives are not cached: {}", url)),
+        }
+    };
 
-            if let Ok(config) = Git2Config::open(global_conf.as_path()) {
-                config.get_string("http.proxy").map(|v| Url::parse(&v).unwrap()).ok()
-            } else {
-                warn!("Cannot locate or open git global configuration");
-                None
+    if args.cmd_list {
+        for entry in cache.entries().unwrap_or_else(|e| fail(e)) {
+            println!("{}\t{}", entry.url, entry.path.display());
+        }
+    } else if args.cmd_update {
+        let urls = match target {
+            Some(url) => vec![url],
+            None => {
+                cache.entries()
+                    .unwrap_or_else(|e| fail(e))
+                    .iter()
+                    .filter_map(|entry| Url::parse(&entry.url).ok())
+                    .collect()
             }
+        };
+        for url in urls {
+            println!("Updating {}", url);
+            if let Err(e) = cache.update(&url, &config.git) {
+                fail(e);
+            }
+        }
+    } else if args.cmd_clean {
+        let removed = match target {
+            Some(url) => cache.remove(&url),
+            None => cache.clear(),
+        };
+        if let Err(e) = removed {
+            fail(e);
+        }
+    }
+}
+
+fn print_versions(repository: &str, config: &UserConfig) {
+    let url = match parse_location(repository, config) {
+        Location::Remote(url, _) => url,
+        Location::Local(path) => {
+            fail(format!("Versions are only available for remote repository: {:?}", path))
+        }
+        Location::Archive(url) => {
+            fail(format!("Versions are only available for remote repository: {}", url))
+        }
+    };
 
-        } else {
-            None
+    for tag in git::list_tags(&url, &config.git).unwrap_or_else(|e| fail(e)) {
+        match git::tag_version(&tag) {
+            Some(version) => println!("{} ({})", tag, version),
+            None => println!("{}", tag),
         }
     }
 }
 
-fn normalize_url(raw: &str) -> Result<Url> {
-    if let Some(_) = raw.find('/') {
-        Url::parse(raw)
-            .or(Url::parse(format!("https://github.com/{}", raw).as_ref()))
-            .map_err(|e| ErrorKind::ParseUrl(e).into())
-    } else {
-        Err(ErrorKind::InvalidUrlFormat(raw.to_string()).into())
+fn search_templates(term: &str, config: &UserConfig) {
+    if config.indexes.is_empty() {
+        fail(format!("No template index is configured, add `index` to {:?}",
+                     config::config_path().unwrap_or_default()));
+    }
+
+    for location in &config.indexes {
+        let index = match Index::load(location) {
+            Ok(index) => index,
+            Err(e) => {
+                warn!("Skipping template index {}: {}", location, e);
+                continue;
+            }
+        };
+        for entry in index.search(term) {
+            println!("{}\t{}\t{}", entry.name, entry.location, entry.description);
+        }
     }
 }
 
-fn collect_params<'a>(name: &'a Option<String>,
-                      params: &'a mut HashMap<String, String>)
-                      -> &'a mut HashMap<String, String> {
+/// Ask values of parameters, except `given` ones.
+/// Built-in variables are refreshed by `builtins` once `name` is answered, so that
+/// defaults computed later see the output directory it decides.
+fn collect_params<'a, F>(given: &[String], params: &'a mut Params, builtins: F) -> &'a mut Params
+    where F: Fn(&Params) -> Table
+{
     let mut s = String::new();
-    for (k, v) in params.iter_mut() {
+    for k in params.keys.clone() {
+        let is_name = k == "name";
 
-        // we treat `name` parameter specially
-        if k == "name" {
-            if let Some(ref arg_name) = *name {
-                *v = arg_name.clone();
-                continue;
+        // tables can not be typed in one line, so they are left as declared
+        if params.get_value(&k).map(|v| v.is_table()).unwrap_or(false) {
+            continue;
+        }
+
+        if given.contains(&k) {
+            continue;
+        }
+
+        // parameters irrelevant to earlier answers are left as default
+        match params.is_asked(&k) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => fail(e),
+        }
+
+        // computed default is shown with values given so far
+        if let Err(e) = params.compute(&k) {
+            fail(e);
+        }
+
+        match params.specs.get(&k).cloned() {
+            Some(spec) => {
+                let value = prompt_spec(&spec, params.get(&k).unwrap());
+                params.insert_value(k, value);
+            }
+            None => {
+                // input not of the declared type is asked again
+                loop {
+                    print!("{} [{}]:", k, params.get(&k).unwrap());
+                    io::stdout().flush().unwrap();
+                    io::stdin().read_line(&mut s).unwrap();
+                    let input = s.trim().to_string();
+                    s.clear();
+                    if input.is_empty() {
+                        break;
+                    }
+                    match params.insert(k.clone(), input) {
+                        Ok(()) => break,
+                        Err(e) => println!("{}", e),
+                    }
+                }
             }
         }
 
-        print!("{} [{}]:", k, v);
-        io::stdout().flush().unwrap();
-        io::stdin().read_line(&mut s).unwrap();
-        if !s.trim().is_empty() {
-            *v = s.trim().to_string();
-            s.clear();
+        if is_name {
+            let table = builtins(params);
+            params.set_builtins(table);
         }
     }
     params
 }
 
+/// Ask value of declared parameter until valid one is given.
+/// Choices are listed as numbered menu, and either the number or the value can be typed.
+fn prompt_spec(spec: &ParamSpec, current: &str) -> Value {
+    if let Some(ref help) = spec.help {
+        println!("{}", help);
+    }
+    for (i, choice) in spec.choices.iter().enumerate() {
+        println!("  {}) {}", i + 1, template::render(choice));
+    }
+
+    let mut s = String::new();
+    loop {
+        print!("{} [{}]:", spec.prompt.as_ref().unwrap_or(&spec.name), current);
+        io::stdout().flush().unwrap();
+        // end of input can not be answered again, so it is not asked forever
+        if io::stdin().read_line(&mut s).unwrap() == 0 {
+            println!("");
+            fail(format!("No answer for `{}`", spec.name));
+        }
+        let input = if s.trim().is_empty() { current } else { s.trim() };
+        match spec.read_input(input) {
+            Ok(value) => return value,
+            Err(reason) => println!("Invalid value: {}", reason),
+        }
+        s.clear();
+    }
+}
+
 fn get_output_dir(arg_name: &Option<String>, default_name: &str) -> PathBuf {
     let mut output_dir = env::current_dir().unwrap();
     if let Some(ref name) = *arg_name {
@@ -0,0 +1,248 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::path::PathBuf;
+use std::str;
+
+use regex::Regex;
+use toml::value::Value;
+use url::Url;
+
+use super::archive;
+use super::errors::*;
+use super::template::Params;
+
+/// Maven Central, where `maven()` directives are resolved by default.
+pub const CENTRAL: &'static str = "https://repo1.maven.org/maven2";
+
+/// Where to look for versions of artifacts.
+///
+/// ```toml
+/// [maven]
+/// repository = "https://nexus.example.com/repository/maven-public"
+/// local = "~/.m2/repository"
+/// ```
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub repository: String,
+    pub local: Option<PathBuf>,
+    /// Look into local repository only.
+    pub offline: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            repository: CENTRAL.to_string(),
+            local: None,
+            offline: false,
+        }
+    }
+}
+
+/// giter8 directive written as default value, which stands for the latest version of a library.
+///
+/// `maven(org.scala-lang, scala-library, stable)` looks for versions in Maven repository,
+/// skipping milestones and release candidates if `stable` is given.
+/// `ls(library, user)` used to query ls.implicit.ly, which is gone.
+/// Either can end with `fallback=<version>` to use when resolution fails.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Directive {
+    Maven {
+        group: String,
+        artifact: String,
+        stable: bool,
+        fallback: Option<String>,
+    },
+    Ls {
+        library: String,
+        user: String,
+        fallback: Option<String>,
+    },
+}
+
+impl Directive {
+    pub fn parse(raw: &str) -> Option<Directive> {
+        let raw = raw.trim();
+        let (name, args) = match raw.find('(') {
+            Some(i) if raw.ends_with(')') => (&raw[..i], &raw[i + 1..raw.len() - 1]),
+            _ => return None,
+        };
+
+        let mut positional = Vec::new();
+        let mut stable = false;
+        let mut fallback = None;
+        for arg in args.split(',').map(|arg| arg.trim()) {
+            if arg == "stable" {
+                stable = true;
+            } else if arg.starts_with("fallback=") {
+                fallback = Some(arg["fallback=".len()..].trim().to_string());
+            } else {
+                positional.push(arg.to_string());
+            }
+        }
+        if positional.len() < 2 || positional.iter().any(|arg| arg.is_empty()) {
+            return None;
+        }
+
+        match name.trim() {
+            "maven" => {
+                Some(Directive::Maven {
+                    group: positional[0].clone(),
+                    artifact: positional[1].clone(),
+                    stable: stable,
+                    fallback: fallback,
+                })
+            }
+            "ls" => {
+                Some(Directive::Ls {
+                    library: positional[0].clone(),
+                    user: positional[1].clone(),
+                    fallback: fallback,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn fallback(&self) -> Option<&str> {
+        match *self {
+            Directive::Maven { ref fallback, .. } |
+            Directive::Ls { ref fallback, .. } => fallback.as_ref().map(|s| s.as_str()),
+        }
+    }
+
+    /// Latest version the directive stands for.
+    pub fn resolve(&self, settings: &Settings) -> Result<String> {
+        match *self {
+            Directive::Maven { ref group, ref artifact, stable, .. } => {
+                let coordinate = format!("{}:{}", group, artifact);
+                let versions = find_versions(group, artifact, settings)?;
+                latest_version(&versions, stable)
+                    .ok_or(ErrorKind::UnresolvedDirective(coordinate).into())
+            }
+            Directive::Ls { ref library, ref user, .. } => {
+                let reason = format!("ls({}, {}): ls.implicit.ly is no longer available",
+                                     library,
+                                     user);
+                Err(ErrorKind::UnresolvedDirective(reason).into())
+            }
+        }
+    }
+}
+
+/// Replace directives in string parameters with versions they stand for, except `given` ones,
+/// which are not looked up at all. Directives failed to resolve are replaced with their
+/// fallback, or left as they are.
+pub fn resolve_params(params: &mut Params, settings: &Settings, given: &[String]) {
+    for key in params.keys.clone() {
+        if given.contains(&key) {
+            continue;
+        }
+        let directive = match params.get_value(&key) {
+            Some(&Value::String(ref s)) => Directive::parse(s),
+            _ => None,
+        };
+        let directive = match directive {
+            Some(directive) => directive,
+            None => continue,
+        };
+
+        match directive.resolve(settings) {
+            Ok(version) => {
+                debug!("Resolved `{}` into {}", key, version);
+                params.insert_value(key, Value::String(version));
+            }
+            Err(e) => {
+                match directive.fallback() {
+                    Some(fallback) => {
+                        warn!("Using fallback {} for `{}`: {}", fallback, key, e);
+                        params.insert_value(key, Value::String(fallback.to_string()));
+                    }
+                    None => warn!("Failed to resolve `{}`: {}", key, e),
+                }
+            }
+        }
+    }
+}
+
+/// Versions of artifact in remote repository, or in local one if offline or remote fails.
+fn find_versions(group: &str, artifact: &str, settings: &Settings) -> Result<Vec<String>> {
+    let dir = format!("{}/{}", group.replace('.', "/"), artifact);
+    let coordinate = format!("{}:{}", group, artifact);
+    let mut error = Error::from(ErrorKind::UnresolvedDirective(coordinate));
+
+    if !settings.offline {
+        let base = settings.repository.trim_right_matches('/');
+        let url = Url::parse(&format!("{}/{}/maven-metadata.xml", base, dir))?;
+        let fetched = archive::fetch(&url).and_then(|content| {
+            str::from_utf8(&content)
+                .map(read_metadata)
+                .map_err(|_| ErrorKind::UnresolvedDirective(url.to_string()).into())
+        });
+        match fetched {
+            Ok(ref versions) if versions.is_empty() => {}
+            Ok(versions) => return Ok(versions),
+            Err(e) => {
+                debug!("Failed to read metadata from {}: {}", url, e);
+                error = e;
+            }
+        }
+    }
+
+    if let Some(ref local) = settings.local {
+        // local repository has a directory for each version
+        let path = local.join(&dir);
+        if let Ok(entries) = fs::read_dir(&path) {
+            let versions = entries.filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .collect::<Vec<_>>();
+            if !versions.is_empty() {
+                return Ok(versions);
+            }
+        }
+    }
+    Err(error)
+}
+
+/// Versions listed in `maven-metadata.xml`.
+pub fn read_metadata(content: &str) -> Vec<String> {
+    let version = Regex::new(r"<version>\s*([^<\s]+)\s*</version>").unwrap();
+    version.captures_iter(content).map(|c| c[1].to_string()).collect()
+}
+
+/// Highest of `versions`, ignoring snapshots, and versions with qualifiers like `-M1` or `-RC2`
+/// if `stable` is set.
+pub fn latest_version(versions: &[String], stable: bool) -> Option<String> {
+    versions.iter()
+        .filter(|v| !v.ends_with("-SNAPSHOT"))
+        .filter(|v| !stable || split_version(v).1.is_empty())
+        .max_by(|a, b| compare_versions(a, b))
+        .cloned()
+}
+
+/// Compare versions by leading numbers, then release is higher than the one with qualifier.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_nums, a_qualifier) = split_version(a);
+    let (b_nums, b_qualifier) = split_version(b);
+    for i in 0..a_nums.len().max(b_nums.len()) {
+        let (x, y) = (a_nums.get(i).unwrap_or(&0), b_nums.get(i).unwrap_or(&0));
+        if x != y {
+            return x.cmp(y);
+        }
+    }
+    match (a_qualifier.is_empty(), b_qualifier.is_empty()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => a_qualifier.cmp(b_qualifier),
+    }
+}
+
+/// Split `1.2.0-RC1` into `[1, 2, 0]` and `RC1`.
+fn split_version(version: &str) -> (Vec<u64>, &str) {
+    let end = version.find(|c: char| !c.is_digit(10) && c != '.').unwrap_or(version.len());
+    let nums = version[..end].split('.').filter_map(|n| n.parse().ok()).collect();
+    let qualifier = version[end..].trim_left_matches(|c| c == '-' || c == '.');
+    (nums, qualifier)
+}
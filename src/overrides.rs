@@ -0,0 +1,152 @@
+use std::collections::HashSet;
+
+use toml::value::{Table, Value};
+
+use super::errors::*;
+use super::template::Params;
+
+/// Prefix of environment variables setting parameters, like `RIG_PARAM_SCALA_VERSION`.
+pub const ENV_PREFIX: &'static str = "RIG_PARAM_";
+
+/// Split `key=value` given to `--param`.
+pub fn parse_pair(raw: &str) -> Option<(String, String)> {
+    match raw.find('=') {
+        Some(i) if i > 0 => Some((raw[..i].to_string(), raw[i + 1..].to_string())),
+        _ => None,
+    }
+}
+
+/// Take giter8 style `--key=value` arguments out of command line, leaving options
+/// appear in `usage` (and everything after `--`) as they are.
+pub fn split_args<I>(args: I, usage: &str) -> (Vec<String>, Vec<(String, String)>)
+    where I: IntoIterator<Item = String>
+{
+    let known = usage.split_whitespace()
+        .filter(|word| word.starts_with("--"))
+        .map(|word| word.trim_right_matches(|c: char| !c.is_alphanumeric()))
+        .map(|word| word.split('=').next().unwrap())
+        .collect::<HashSet<_>>();
+
+    let mut rest = Vec::new();
+    let mut pairs = Vec::new();
+    let mut options_end = false;
+    for arg in args {
+        if arg == "--" {
+            options_end = true;
+        }
+        if !options_end && arg.starts_with("--") {
+            if let Some((key, value)) = parse_pair(&arg[2..]) {
+                if !known.contains(format!("--{}", key).as_str()) {
+                    pairs.push((key, value));
+                    continue;
+                }
+            }
+        }
+        rest.push(arg);
+    }
+    (rest, pairs)
+}
+
+/// Parameters given by `RIG_PARAM_<KEY>` environment variables.
+/// `<KEY>` matches declared keys ignoring case. Variables matching none are ignored
+/// with warning, as they may be meant for other templates.
+pub fn env_pairs<I>(vars: I, keys: &[String]) -> Vec<(String, String)>
+    where I: IntoIterator<Item = (String, String)>
+{
+    let mut pairs = vars.into_iter()
+        .filter(|&(ref name, _)| name.starts_with(ENV_PREFIX) && name.len() > ENV_PREFIX.len())
+        .filter_map(|(name, value)| {
+            let raw = &name[ENV_PREFIX.len()..];
+            let key = keys.iter().find(|key| key.to_lowercase() == raw.to_lowercase()).cloned();
+            if key.is_none() {
+                warn!("Ignoring `{}`, as the template has no such parameter", name);
+            }
+            key.map(|key| (key, value))
+        })
+        .collect::<Vec<_>>();
+    // environment has no order, so keep the result stable
+    pairs.sort();
+    pairs
+}
+
+/// Set values given outside of template, and return keys set, which are not asked again.
+/// Keys must be parameters of the template, so that typos are not taken as new parameters.
+/// Values of parameters declared in `[params.<name>]` must follow the declaration.
+pub fn apply<I>(params: &mut Params, pairs: I) -> Result<Vec<String>>
+    where I: IntoIterator<Item = (String, String)>
+{
+    let mut given = Vec::new();
+    for (key, raw) in pairs {
+        if !params.keys.contains(&key) {
+            let reason = "the template has no such parameter".to_string();
+            return Err(ErrorKind::InvalidParam(key, reason).into());
+        }
+        params.insert(key.clone(), raw)?;
+        if !given.contains(&key) {
+            given.push(key);
+        }
+    }
+    Ok(given)
+}
+
+/// Replace defaults of parameters the template declares with values in user's profile,
+/// and return keys set. They are still asked, unlike other values given outside of template,
+/// but they are not missing when nobody can be asked.
+pub fn prefill(params: &mut Params, profile: &Table) -> Vec<String> {
+    let mut filled = Vec::new();
+    for key in params.keys.clone() {
+        let value = match profile.get(&key) {
+            Some(value) => value.clone(),
+            None => continue,
+        };
+        if let Some(spec) = params.specs.get(&key) {
+            if let Err(reason) = spec.validate(&value) {
+                warn!("Ignoring `{}` in user defaults: {}", key, reason);
+                continue;
+            }
+        }
+        params.insert_value(key.clone(), value);
+        filled.push(key);
+    }
+    filled
+}
+
+/// Set typed values, like ones read from answers file, and return keys set.
+/// As in `apply`, keys must be parameters of the template, and declared values are validated.
+pub fn apply_values<I>(params: &mut Params, pairs: I) -> Result<Vec<String>>
+    where I: IntoIterator<Item = (String, Value)>
+{
+    let mut given = Vec::new();
+    for (key, value) in pairs {
+        if !params.keys.contains(&key) {
+            let reason = "the template has no such parameter".to_string();
+            return Err(ErrorKind::InvalidParam(key, reason).into());
+        }
+        if let Some(spec) = params.specs.get(&key) {
+            spec.validate(&value).map_err(|reason| ErrorKind::InvalidParam(key.clone(), reason))?;
+        }
+        params.insert_value(key.clone(), value);
+        if !given.contains(&key) {
+            given.push(key);
+        }
+    }
+    Ok(given)
+}
+
+/// Parameters declared in `[params.<name>]` without default value, which are not `given`,
+/// either as answers or from user's profile.
+/// Ones whose `when` condition does not hold are not needed.
+pub fn missing(params: &Params, given: &[String]) -> Result<Vec<String>> {
+    let mut missing = Vec::new();
+    for key in &params.keys {
+        let declared = params.toml.as_ref().map(|t| t.contains_key(key)).unwrap_or(false);
+        let no_default = params.specs
+            .get(key)
+            .map(|spec| spec.default.is_none() && !declared)
+            .unwrap_or(false);
+        if no_default && !given.contains(key) && params.is_asked(key)? {
+            missing.push(key.clone());
+        }
+    }
+    Ok(missing)
+}
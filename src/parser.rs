@@ -5,7 +5,7 @@ use combine::primitives::Consumed;
 use super::template::*;
 
 /// Intermediate state of parsing template
-pub type Progress<'a> = (String, Option<Placeholder>, &'a str);
+pub type Progress<'a> = (String, Option<Token>, &'a str);
 
 pub fn parse_template<'a>(tpl: &'a str, style: &'a Style)
     -> Result<Progress<'a>, ParseError<&'a str>>
@@ -17,10 +17,21 @@ pub fn parse_template<'a>(tpl: &'a str, style: &'a Style)
     }
 }
 
+/// Condition of `$if(...)$` without the outermost parentheses,
+/// which may have nested ones like `(a || b) && c`.
+fn condition(input: &str) -> ParseResult<String, &str> {
+    let text = many1::<String, _>(satisfy(|c| c != '(' && c != ')'));
+    let group = parser(condition).map(|inner| format!("({})", inner));
+    between(char('('), char(')'), many::<Vec<String>, _>(text.or(group)))
+        .map(|parts| parts.concat())
+        .parse_stream(input)
+}
+
 /// Parse template written in `StringTemplate` like format
 fn parse_st(input: &str) -> Result<Progress, ParseError<&str>> {
 
-    let ident = || many1::<String, _>(alpha_num().or(one_of("_-".chars())).skip(spaces()));
+    // `.` is for built-in variables like `rig.year`
+    let ident = || many1::<String, _>(alpha_num().or(one_of("_-.".chars())).skip(spaces()));
     let lex_char = |c| char(c).skip(spaces());
 
     let escape_ph = many::<String, _>(satisfy(|c| c != '$').then(|c| {
@@ -38,11 +49,19 @@ fn parse_st(input: &str) -> Result<Progress, ParseError<&str>> {
 
     let string_literal = between(char('"'), char('"'), many::<String, _>(satisfy(|c| c != '"')));
     let fmt_args = string("format").skip(spaces()).with(lex_char('=').with(string_literal));
-    let placeholder = between(
-        lex_char('$'),
-        char('$'),
-        ident().and(optional(char(';').with(fmt_args))))
-        .map(|parsed| Placeholder::new(&parsed.0, parsed.1, Style::ST));
+    let variable = ident()
+        .and(optional(char(';').with(fmt_args)))
+        .map(|parsed| Token::Placeholder(Placeholder::new(&parsed.0, parsed.1, Style::ST)));
+
+    // `$if(cond)$`, `$elseif(cond)$`, `$else$` and `$endif$` of conditional blocks,
+    // which must be tried before variables of the same names
+    let keyword = |name| string(name).skip(spaces()).skip(look_ahead(char('$')));
+    let control = try(string("if").with(parser(condition)).map(Token::If))
+        .or(try(string("elseif").with(parser(condition)).map(Token::ElseIf)))
+        .or(try(keyword("else")).map(|_| Token::Else))
+        .or(try(keyword("endif")).map(|_| Token::EndIf));
+
+    let placeholder = between(lex_char('$'), char('$'), control.or(variable));
 
     let mut parser = escape_ph.and(optional(placeholder));
     parser.parse(input).map(|(result, rest)| (result.0, result.1, rest))
@@ -78,7 +97,8 @@ fn parse_pathname(input: &str) -> Result<Progress, ParseError<&str>> {
             } else {
                 Placeholder::new(&parsed, None, Style::Path)
             }
-        });
+        })
+        .map(Token::Placeholder);
 
     let mut parser = escape_ph.and(optional(placeholder));
     parser.parse(input).map(|(result, rest)| (result.0, result.1, rest))
@@ -0,0 +1,125 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use atty;
+use git2::Progress;
+
+/// Minimum interval between redraws of progress line on terminal, in milliseconds.
+const REDRAW_INTERVAL_MS: u64 = 100;
+
+/// Interval between progress lines when stderr is not a terminal, in seconds.
+const LOG_INTERVAL_SECS: u64 = 5;
+
+/// Reports progress of fetching templates and writing files to stderr.
+///
+/// On terminal, a single line is redrawn in place. Otherwise, as in CI logs,
+/// a line is written every few seconds.
+pub struct Reporter {
+    label: String,
+    tty: bool,
+    last: Option<Instant>,
+    done: bool,
+    out: Box<Write>,
+}
+
+impl Reporter {
+    pub fn new<S: Into<String>>(label: S) -> Reporter {
+        Reporter::to_writer(label, atty::is(atty::Stream::Stderr), Box::new(io::stderr()))
+    }
+
+    /// Report into `out` instead of stderr, as on terminal if `tty`.
+    pub fn to_writer<S: Into<String>>(label: S, tty: bool, out: Box<Write>) -> Reporter {
+        Reporter {
+            label: label.into(),
+            tty: tty,
+            last: None,
+            done: false,
+            out: out,
+        }
+    }
+
+    /// Report objects received and deltas resolved so far.
+    pub fn transfer(&mut self, stats: &Progress) {
+        let (received, total) = (stats.received_objects(), stats.total_objects());
+        let (deltas, total_deltas) = (stats.indexed_deltas(), stats.total_deltas());
+        if total == 0 {
+            return;
+        }
+
+        let line = if received < total {
+            format!("{}: receiving objects {}% ({}/{}), {}",
+                    self.label,
+                    percent(received, total),
+                    received,
+                    total,
+                    human_bytes(stats.received_bytes()))
+        } else {
+            format!("{}: resolving deltas {}% ({}/{})",
+                    self.label,
+                    percent(deltas, total_deltas),
+                    deltas,
+                    total_deltas)
+        };
+        self.report(&line, received == total && deltas == total_deltas);
+    }
+
+    /// Report files written so far, by checkout or project generation.
+    pub fn files(&mut self, current: usize, total: usize) {
+        if total == 0 {
+            return;
+        }
+        let line = format!("{}: writing files {}% ({}/{})",
+                           self.label,
+                           percent(current, total),
+                           current,
+                           total);
+        self.report(&line, current == total);
+    }
+
+    fn report(&mut self, line: &str, done: bool) {
+        if self.done {
+            return;
+        }
+
+        let interval = if self.tty {
+            Duration::from_millis(REDRAW_INTERVAL_MS)
+        } else {
+            Duration::from_secs(LOG_INTERVAL_SECS)
+        };
+        let now = Instant::now();
+        let due = self.last.map(|last| now.duration_since(last) >= interval).unwrap_or(true);
+        if !due && !done {
+            return;
+        }
+
+        let _ = if self.tty {
+            // `\x1b[K` clears rest of the previous, possibly longer, line
+            write!(self.out, "\r{}\x1b[K{}", line, if done { "\n" } else { "" })
+        } else {
+            writeln!(self.out, "{}", line)
+        };
+        let _ = self.out.flush();
+
+        self.last = Some(now);
+        self.done = done;
+    }
+}
+
+fn percent(current: usize, total: usize) -> usize {
+    if total == 0 { 100 } else { current * 100 / total }
+}
+
+fn human_bytes(bytes: usize) -> String {
+    const UNITS: &'static [&'static str] = &["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.2} {}", size, UNITS[unit])
+    }
+}
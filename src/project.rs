@@ -2,17 +2,19 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str;
 
-use java_properties;
-use tera::{Context, Tera};
-use toml;
-use walkdir::{DirEntry, WalkDir, WalkDirIterator};
+use java_properties::PropertiesIter;
+use regex::{self, Regex};
+use tera::{self, Tera};
 
 use super::errors::*;
 use super::filters;
 use super::fsutils;
+use super::progress::Reporter;
+use super::source::{DirSource, Entry, Source};
 use super::template::{Style, Params, Template};
 
 #[derive(Debug)]
@@ -21,6 +23,8 @@ pub struct Project {
     pub config: Configuration,
     pub style: Style,
     pub force_packaged: bool,
+    /// Report progress of writing files to stderr.
+    pub progress: bool,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -36,6 +40,7 @@ impl Default for Project {
             config: Configuration::Toml,
             style: Style::Tera,
             force_packaged: false,
+            progress: false,
         }
     }
 }
@@ -49,6 +54,7 @@ impl Project {
             config: config,
             style: Style::Tera,
             force_packaged: packaged,
+            progress: false,
         }
     }
 
@@ -58,6 +64,7 @@ impl Project {
             config: Configuration::JavaProps,
             style: Style::ST,
             force_packaged: true,
+            progress: false,
         }
     }
 
@@ -74,54 +81,70 @@ impl Project {
     }
 
     pub fn resolve_root_dir(&self, clone_root: &Path) -> PathBuf {
-        let mut buf = clone_root.to_path_buf();
+        clone_root.join(self.template_dir(&DirSource::new(clone_root)))
+    }
 
-        if let Some(ref inner) = self.root_path {
-            if fsutils::exists(clone_root.join(inner)) {
-                buf.push(inner);
-            }
+    /// Directory where template lives in `source`, relative to its top.
+    pub fn template_dir(&self, source: &Source) -> PathBuf {
+        match self.root_path {
+            Some(ref inner) if source.is_dir(Path::new(inner)) => PathBuf::from(inner),
+            _ => PathBuf::new(),
         }
-        buf
     }
 
     pub fn default_params(&self, clone_root: &Path) -> Result<Params> {
-        let root = self.resolve_root_dir(clone_root);
-        get_defaults(self, &root)
+        self.default_params_from(&DirSource::new(clone_root))
+    }
+
+    pub fn default_params_from(&self, source: &Source) -> Result<Params> {
+        let root = self.template_dir(source);
+        get_defaults(self, source, &root)
     }
 
-    // TODO: give clear `Err` type
-    // TODO: make it run async
     pub fn generate(&self,
                     params: &Params,
                     clone_root: &Path,
                     dest: &Path,
                     dry_run: bool)
                     -> Result<()> {
+        self.generate_from(params, &DirSource::new(clone_root), dest, dry_run)
+    }
 
-        let root = self.resolve_root_dir(clone_root);
-        let walker = WalkDir::new(&root).into_iter();
+    // TODO: make it run async
+    pub fn generate_from(&self,
+                         params: &Params,
+                         source: &Source,
+                         dest: &Path,
+                         dry_run: bool)
+                         -> Result<()> {
+
+        let root = self.template_dir(source);
 
         let mut name_map: HashMap<OsString, String> = HashMap::new();
-        let mut tree: Vec<(DirEntry, PathBuf)> = Vec::new();
+        let mut tree: Vec<(Entry, PathBuf)> = Vec::new();
         let default_file = root.join(self.config_name());
 
-        for entry in walker.filter_entry(|e| !is_git_metadata(e)) {
-            let entry = entry.unwrap();
-
-            if entry.path() == &root || entry.path() == &default_file {
-                debug!("skipping {:?}", entry.file_name());
+        for entry in source.entries(&root)? {
+            if entry.path == default_file {
+                debug!("skipping {:?}", entry.path);
                 continue;
             }
 
-            &tree.push((entry.clone(), resolve_dirname(self, &entry, dest, &mut name_map, params)));
+            let rel_path = entry.path.strip_prefix(&root).unwrap().to_path_buf();
+            let entry_dest = resolve_dirname(self, &rel_path, dest, &mut name_map, params)?;
+            tree.push((entry, entry_dest));
 
         }
-        // TODO:
         if !dry_run {
-            fs::create_dir_all(dest).unwrap();
+            fs::create_dir_all(dest)?;
+            let reporter = if self.progress {
+                Some(Reporter::new(format!("Generating {}", dest.display())))
+            } else {
+                None
+            };
             match self.style {
-                Style::Tera => self.generate_with_tera(params, tree),
-                _ => self.generate_tree(params, tree)
+                Style::Tera => self.generate_with_tera(params, source, tree, reporter)?,
+                _ => self.generate_tree(params, source, tree, reporter)?,
             }
         }
         debug!("{:?}", &name_map);
@@ -129,104 +152,157 @@ impl Project {
         Ok(())
     }
 
-    fn generate_tree(&self, params: &Params, tree: Vec<(DirEntry, PathBuf)>) {
+    fn generate_tree(&self,
+                     params: &Params,
+                     source: &Source,
+                     tree: Vec<(Entry, PathBuf)>,
+                     mut reporter: Option<Reporter>)
+                     -> Result<()> {
 
+        let total = count_files(&tree);
+        let mut written = 0;
         for loc in tree {
             let (src, dest) = loc;
 
-            if src.file_type().is_file() {
+            if src.is_file() {
 
                 let mut f = fs::OpenOptions::new()
                     .write(true)
                     .truncate(true)
                     .create(true)
-                    .open(dest.as_path())
-                    .unwrap();
-
-                let mut tpl = Template::read_file(self.style.clone(),
-                                                  &src.path())
-                    .unwrap();
-                tpl.write_to(&mut f, &params.param_map).unwrap();
-                f.sync_data().unwrap();
-
-            } else if src.file_type().is_dir() {
-                fs::create_dir_all(dest.as_path()).expect("Creating directory");
+                    .open(dest.as_path())?;
+
+                // binary files can not be templates, copy them as is
+                match String::from_utf8(source.read(&src.path)?) {
+                    Ok(content) => {
+                        let mut tpl = Template {
+                            path: Some(src.path.clone()),
+                            ..Template::read_str(self.style.clone(), content)
+                        };
+                        tpl.write_to(&mut f, &params.param_map)?;
+                    }
+                    Err(e) => f.write_all(e.as_bytes())?,
+                }
+                f.sync_data()?;
+
+                written += 1;
+                if let Some(ref mut reporter) = reporter {
+                    reporter.files(written, total);
+                }
+
+            } else if src.is_dir() {
+                fs::create_dir_all(dest.as_path())?;
             }
         }
+        Ok(())
     }
 
     fn generate_with_tera(&self,
                           params: &Params,
-                          tree: Vec<(DirEntry, PathBuf)>) {
+                          source: &Source,
+                          tree: Vec<(Entry, PathBuf)>,
+                          mut reporter: Option<Reporter>)
+                          -> Result<()> {
 
         let mut tera = Tera::default();
-        let mut ctx = Context::new();
-        init_tera_filters(&mut tera);
-
-        // TODO: which toml table will be used in context?
-        for (k, v) in &params.param_map {
-            &ctx.add(&k, &v);
-        }
+        let ctx = params.tera_context();
+        let mut verbatim: HashMap<PathBuf, Vec<u8>> = HashMap::new();
+        filters::register(&mut tera);
 
         for ref loc in &tree {
             let (ref src, ref dest) = **loc;
-            if src.file_type().is_file() {
-                tera.add_template_file(&src.path(),
-                                       Some(dest.to_string_lossy().as_ref()))
-                    .unwrap();
+            if src.is_file() {
+                match String::from_utf8(source.read(&src.path)?) {
+                    Ok(content) => {
+                        tera.add_raw_template(dest.to_string_lossy().as_ref(), &content)
+                            .map_err(|e| tera_error(e, &src.path, &content))?
+                    }
+                    Err(e) => {
+                        verbatim.insert(dest.clone(), e.into_bytes());
+                    }
+                }
             }
         }
         debug!("{:?}", &tera.templates);
 
+        let total = count_files(&tree);
+        let mut written = 0;
         for loc in tree {
             let (src, dest) = loc;
             debug!("{:?} => {:?}", &src, &dest);
 
-            if src.file_type().is_file() {
-
-                let content = tera
-                    .render(dest.to_string_lossy().as_ref(), ctx.clone())
-                    .unwrap();
-
-                fsutils::write_file(&dest, &content).unwrap();
+            if src.is_file() {
+                if let Some(content) = verbatim.remove(&dest) {
+                    fsutils::write_bytes(&dest, &content)?;
+                } else {
+                    let name = dest.to_string_lossy();
+                    let content = match tera.render(name.as_ref(), ctx.clone()) {
+                        Ok(content) => content,
+                        Err(e) => {
+                            let body = source.read(&src.path)?;
+                            return Err(tera_error(e, &src.path, &String::from_utf8_lossy(&body)));
+                        }
+                    };
+                    fsutils::write_file(&dest, &content)?;
+                }
+
+                written += 1;
+                if let Some(ref mut reporter) = reporter {
+                    reporter.files(written, total);
+                }
             } else {
-                fs::create_dir_all(dest.as_path()).expect("Creating directory");
+                fs::create_dir_all(dest.as_path())?;
             }
         }
+        Ok(())
     }
 }
 
-fn is_git_metadata(entry: &DirEntry) -> bool {
-    let is_git = entry.file_name().to_str().map(|s| s == ".git").unwrap_or(false);
-    fsutils::is_directory(entry.path()) && is_git
+/// Number of files to write, as progress is reported in files.
+fn count_files(tree: &[(Entry, PathBuf)]) -> usize {
+    tree.iter().filter(|&&(ref src, _)| src.is_file()).count()
+}
+
+/// Error of Tera as `RenderFailure`. Tera tells line and column of syntax errors,
+/// and fields missing in context are located by their first use in `body`.
+fn tera_error(err: tera::Error, path: &Path, body: &str) -> Error {
+    let reason = err.iter().last().map(|e| e.to_string()).unwrap_or(String::new());
+    let messages = err.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n");
+
+    let position = Regex::new(r"line (\d+), col(?:umn)? (\d+)").unwrap();
+    let missing = Regex::new(r"Field `([^`]+)` not found").unwrap();
+    let path = path.to_path_buf();
+
+    let error = if let Some(caps) = position.captures(&messages) {
+        let line = caps[1].parse().unwrap_or(0);
+        let column = caps[2].parse().unwrap_or(0);
+        RenderError::new(path, body, line, column, reason)
+    } else if let Some(caps) = missing.captures(&messages) {
+        let use_site = format!(r"\{{[{{%][^}}]*?\b({})\b", regex::escape(&caps[1]));
+        match Regex::new(&use_site).ok().and_then(|re| re.captures(body)) {
+            Some(found) => {
+                RenderError::at_offset(path, body, found.get(1).unwrap().start(), reason)
+            }
+            None => RenderError::new(path, body, 0, 0, reason),
+        }
+    } else {
+        RenderError::new(path, body, 0, 0, reason)
+    };
+    ErrorKind::RenderFailure(error).into()
 }
 
 fn resolve_dirname(project: &Project,
-                   entry: &DirEntry,
+                   rel_path: &Path,
                    dest_root: &Path,
                    alt_paths: &mut HashMap<OsString, String>,
                    params: &Params)
-                   -> PathBuf
+                   -> Result<PathBuf>
 {
 
-    let mut segment: Vec<&OsStr> = Vec::new();
-    let mut rel_path_up = entry.path().parent();
-    let mut upwards = 1;
-    while let Some(parent) = rel_path_up {
-        if upwards >= entry.depth() {
-            break;
-        } else {
-            segment.push(parent.file_name().unwrap_or("".as_ref()));
-        }
-        upwards += 1;
-        rel_path_up = parent.parent();
-    }
-
-    let base = entry.file_name();
+    let base = rel_path.file_name().unwrap_or(OsStr::new(""));
     let mut dest = dest_root.to_path_buf();
-    if !segment.is_empty() {
-        segment.reverse();
-        for part in segment {
+    if let Some(parent) = rel_path.parent() {
+        for part in parent.iter() {
             if let Some(rep) = alt_paths.get(part) {
                 debug!("File tree altered: {:?} => {:?}", part, rep);
                 dest.push(rep);
@@ -242,55 +318,45 @@ fn resolve_dirname(project: &Project,
     if pkg.as_ref() == "$package$" && project.force_packaged {
         pkg = Cow::from("$package__packaged$");
     }
-    Template::write_once(&mut buf,
-                         Style::Path,
-                         pkg,
-                         &params.param_map)
-        .unwrap();
+    let mut tpl = Template {
+        path: Some(rel_path.to_path_buf()),
+        ..Template::read_str(Style::Path, pkg)
+    };
+    tpl.write_to(&mut buf, &params.param_map)?;
 
-    let name = String::from_utf8(buf).unwrap();
+    let name = String::from_utf8_lossy(&buf).into_owned();
     if &name != base.to_string_lossy().as_ref() {
         alt_paths.insert(base.to_os_string(), name.clone());
     }
     dest.push(&name);
     debug!("Destination entry: {:?}", dest);
 
-    dest
+    Ok(dest)
 }
 
-fn get_defaults(project: &Project, root_dir: &Path) -> Result<Params> {
+fn get_defaults(project: &Project, source: &Source, root_dir: &Path) -> Result<Params> {
     let defaults_file = root_dir.join(project.config_name());
+    let content = source.read(&defaults_file)?;
 
     // TODO: get default value from specific toml table if there is any
     match project.config {
         Configuration::JavaProps => {
-            fs::File::open(&defaults_file)
-                .map(|f| {
-                    let props = java_properties::read(f).unwrap();
-                    Params::from_map(props)
-                })
-                .map_err(|e| ErrorKind::Io(e).into()) // Should convert ParseError
+            let mut props = Vec::new();
+            PropertiesIter::new(&content[..])
+                .read_into(|k, v| props.push((k, v)))
+                .map_err(|e| {
+                    let reason = format!("{}: {}", defaults_file.display(), e);
+                    Error::from(ErrorKind::PropertiesDecodeFailure(reason))
+                })?;
+            let mut params = Params::from_pairs(props);
+            params.read_computed(Style::ST);
+            Ok(params)
         }
         Configuration::Toml => {
-            fsutils::read_file(&defaults_file)
-                .map(|s| {
-                    let tbl: toml::value::Table = toml::from_str(&s).unwrap();
-                    Params::convert_toml(tbl)
-                })
-                .chain_err(|| ErrorKind::TomlDecodeFailure)
+            let content = str::from_utf8(&content).chain_err(|| ErrorKind::TomlDecodeFailure)?;
+            let mut params = Params::read_toml(content)?;
+            params.read_computed(Style::Tera);
+            Ok(params)
         }
     }
 }
-
-fn init_tera_filters(tera: &mut Tera) {
-    tera.register_filter("decap", filters::decap);
-    tera.register_filter("word", filters::word);
-    tera.register_filter("hyphen", filters::hyphen);
-    tera.register_filter("start", filters::start);
-    tera.register_filter("Camel", filters::upper_camel);
-    tera.register_filter("camel", filters::lower_camel);
-    tera.register_filter("norm", filters::norm);
-    tera.register_filter("snake", filters::snake);
-    tera.register_filter("packaged", filters::packaged);
-    tera.register_filter("random", filters::random);
-}
\ No newline at end of file
@@ -0,0 +1,230 @@
+use regex::Regex;
+use toml::value::{Table, Value};
+
+use super::errors::*;
+use super::template;
+
+/// Type of parameter value, declared by `type` or guessed from `default`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParamType {
+    String,
+    Boolean,
+    Integer,
+    Float,
+    Array,
+}
+
+impl ParamType {
+    pub fn from_name(name: &str) -> Option<ParamType> {
+        match name {
+            "string" | "str" => Some(ParamType::String),
+            "boolean" | "bool" => Some(ParamType::Boolean),
+            "integer" | "int" => Some(ParamType::Integer),
+            "float" => Some(ParamType::Float),
+            "array" | "list" => Some(ParamType::Array),
+            _ => None,
+        }
+    }
+
+    /// Type of `value`, if parameters can hold it.
+    pub fn of(value: &Value) -> Option<ParamType> {
+        match *value {
+            Value::String(_) => Some(ParamType::String),
+            Value::Boolean(_) => Some(ParamType::Boolean),
+            Value::Integer(_) => Some(ParamType::Integer),
+            Value::Float(_) => Some(ParamType::Float),
+            Value::Array(_) => Some(ParamType::Array),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match *self {
+            ParamType::String => "string",
+            ParamType::Boolean => "boolean",
+            ParamType::Integer => "integer",
+            ParamType::Float => "float",
+            ParamType::Array => "array",
+        }
+    }
+
+    /// Read user input as this type.
+    fn parse(&self, raw: &str) -> Option<Value> {
+        match *self {
+            ParamType::String => Some(Value::String(raw.to_string())),
+            ParamType::Boolean => {
+                match raw.to_lowercase().as_ref() {
+                    "true" | "yes" | "y" => Some(Value::Boolean(true)),
+                    "false" | "no" | "n" => Some(Value::Boolean(false)),
+                    _ => None,
+                }
+            }
+            ParamType::Integer => raw.parse().ok().map(Value::Integer),
+            ParamType::Float => raw.parse().ok().map(Value::Float),
+            ParamType::Array => {
+                let items = raw.split(',')
+                    .map(|item| item.trim())
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_string()))
+                    .collect();
+                Some(Value::Array(items))
+            }
+        }
+    }
+}
+
+/// Declaration of a parameter in `[params.<name>]` table of `Rig.toml`.
+///
+/// ```toml
+/// [params.license]
+/// prompt = "License of the project"
+/// help = "SPDX identifier, see https://spdx.org/licenses/"
+/// choices = ["MIT", "Apache-2.0"]
+/// default = "MIT"
+///
+/// [params.crate_name]
+/// pattern = "[a-z][a-z0-9_]*"
+///
+/// [params.docker_registry]
+/// default = "registry.example.com"
+/// when = "use_docker"
+/// ```
+#[derive(Clone, Debug)]
+pub struct ParamSpec {
+    pub name: String,
+    pub default: Option<Value>,
+    pub prompt: Option<String>,
+    pub help: Option<String>,
+    pub kind: ParamType,
+    pub choices: Vec<Value>,
+    pub pattern: Option<Regex>,
+    /// Tera condition over earlier values, like `use_docker and env != "dev"`.
+    /// The parameter is not asked unless it holds, and keeps its default.
+    pub when: Option<String>,
+}
+
+impl ParamSpec {
+    pub fn from_toml(name: &str, table: &Table) -> Result<ParamSpec> {
+        let invalid = |reason: String| Error::from(ErrorKind::InvalidParam(name.into(), reason));
+        let string = |key: &str| -> Result<Option<String>> {
+            match table.get(key) {
+                Some(&Value::String(ref s)) => Ok(Some(s.clone())),
+                Some(_) => Err(invalid(format!("`{}` must be a string", key))),
+                None => Ok(None),
+            }
+        };
+
+        let default = table.get("default").cloned();
+        let choices = match table.get("choices") {
+            Some(&Value::Array(ref items)) => items.clone(),
+            Some(_) => return Err(invalid("`choices` must be an array".into())),
+            None => Vec::new(),
+        };
+
+        // without `type`, guess it from default value or the first choice
+        let kind = match string("type")? {
+            Some(name) => {
+                ParamType::from_name(&name).ok_or(invalid(format!("unknown type `{}`", name)))?
+            }
+            None => {
+                default.as_ref()
+                    .or(choices.first())
+                    .and_then(ParamType::of)
+                    .unwrap_or(ParamType::String)
+            }
+        };
+
+        let pattern = match string("pattern")? {
+            Some(p) => {
+                // pattern must match whole input, not a part of it
+                let full = format!("^(?:{})$", p);
+                Some(Regex::new(&full).map_err(|e| invalid(format!("invalid pattern: {}", e)))?)
+            }
+            None => None,
+        };
+
+        let spec = ParamSpec {
+            name: name.to_string(),
+            default: default,
+            prompt: string("prompt")?,
+            help: string("help")?,
+            kind: kind,
+            choices: choices,
+            pattern: pattern,
+            when: string("when")?,
+        };
+        for choice in &spec.choices {
+            if ParamType::of(choice) != Some(spec.kind) {
+                return Err(invalid(format!("choice `{}` is not {}",
+                                           template::render(choice),
+                                           spec.kind.name())));
+            }
+        }
+        if let Some(ref default) = spec.default {
+            spec.validate(default).map_err(|reason| invalid(format!("default: {}", reason)))?;
+        }
+        Ok(spec)
+    }
+
+    /// Value used when neither `default` nor top level value is declared.
+    pub fn fallback(&self) -> Value {
+        match self.default {
+            Some(ref default) => default.clone(),
+            None => {
+                match self.kind {
+                    ParamType::Boolean => Value::Boolean(false),
+                    ParamType::Integer => Value::Integer(0),
+                    ParamType::Float => Value::Float(0.0),
+                    ParamType::Array => Value::Array(Vec::new()),
+                    ParamType::String => {
+                        self.choices.first().cloned().unwrap_or(Value::String(String::new()))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Read user input, which may be the number of a choice in the menu,
+    /// and check it is valid. Error tells why the input is rejected.
+    pub fn read_input(&self, raw: &str) -> ::std::result::Result<Value, String> {
+        let raw = raw.trim();
+        let is_choice = self.choices.iter().any(|c| template::render(c) == raw);
+        if !is_choice {
+            match raw.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= self.choices.len() => {
+                    return Ok(self.choices[n - 1].clone());
+                }
+                _ => {}
+            }
+        }
+
+        let value = self.kind
+            .parse(raw)
+            .ok_or(format!("`{}` is not {}", raw, self.kind.name()))?;
+        self.validate(&value)?;
+        Ok(value)
+    }
+
+    /// Check `value` has the declared type, is one of choices and matches pattern.
+    pub fn validate(&self, value: &Value) -> ::std::result::Result<(), String> {
+        let rendered = template::render(value);
+        if ParamType::of(value) != Some(self.kind) {
+            return Err(format!("`{}` is not {}", rendered, self.kind.name()));
+        }
+        if !self.choices.is_empty() && !self.choices.contains(value) {
+            let choices = self.choices.iter().map(template::render).collect::<Vec<_>>();
+            return Err(format!("`{}` is not one of {}", rendered, choices.join(", ")));
+        }
+        if let Some(ref pattern) = self.pattern {
+            // array items are checked one by one
+            let items = match *value {
+                Value::Array(ref items) => items.iter().map(template::render).collect(),
+                _ => vec![rendered],
+            };
+            if let Some(item) = items.iter().find(|item| !pattern.is_match(item)) {
+                return Err(format!("`{}` does not match pattern {}", item, pattern));
+            }
+        }
+        Ok(())
+    }
+}
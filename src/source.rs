@@ -0,0 +1,419 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use git2::{Config, ObjectType, Oid, Repository, Tree};
+use tempdir::TempDir;
+use url::Url;
+use walkdir::{WalkDir, WalkDirIterator};
+
+use super::errors::*;
+use super::fsutils;
+use super::git;
+use super::location::{Hosts, Revision};
+
+/// Kind of entries in template source.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EntryKind {
+    File,
+    Dir,
+}
+
+/// An entry in template source, with path relative to the top of the source.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub kind: EntryKind,
+}
+
+impl Entry {
+    pub fn file<P: Into<PathBuf>>(path: P) -> Entry {
+        Entry { path: path.into(), kind: EntryKind::File }
+    }
+
+    pub fn dir<P: Into<PathBuf>>(path: P) -> Entry {
+        Entry { path: path.into(), kind: EntryKind::Dir }
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == EntryKind::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Dir
+    }
+}
+
+/// Tree of template files, which `Project` generates new project from.
+///
+/// All paths are relative to the top of the source.
+pub trait Source {
+    /// List all entries under `dir`, excluding `dir` itself.
+    /// Parent directories come before their children.
+    fn entries(&self, dir: &Path) -> Result<Vec<Entry>>;
+
+    /// Read content of the file at `path`.
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    fn is_dir(&self, path: &Path) -> bool;
+
+    fn is_file(&self, path: &Path) -> bool;
+}
+
+/// Template files on local file system, like working tree of git repository.
+#[derive(Clone, Debug)]
+pub struct DirSource {
+    pub root: PathBuf,
+}
+
+impl DirSource {
+    pub fn new<P: Into<PathBuf>>(root: P) -> DirSource {
+        DirSource { root: root.into() }
+    }
+}
+
+impl Source for DirSource {
+    fn entries(&self, dir: &Path) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        let walker = WalkDir::new(self.root.join(dir)).min_depth(1).into_iter();
+
+        for entry in walker.filter_entry(|e| e.file_name() != ".git") {
+            let entry = entry.map_err(|e| Error::from(e.to_string()))?;
+            let path = entry.path().strip_prefix(&self.root).unwrap().to_path_buf();
+            if entry.file_type().is_dir() {
+                entries.push(Entry::dir(path));
+            } else {
+                entries.push(Entry::file(path));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        fs::File::open(self.root.join(path))?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        fsutils::is_directory(self.root.join(path))
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.root.join(path).is_file()
+    }
+}
+
+/// Template files in a commit of git repository, read without checking them out.
+///
+/// Works with bare repositories as well, like mirrors in template cache.
+/// Submodules are empty directories until they are loaded with `load_submodules`.
+pub struct GitSource {
+    repo: Repository,
+    commit: Oid,
+    tree: Oid,
+    mounts: Vec<(PathBuf, GitSource)>,
+}
+
+/// Submodule recorded in a commit, with URL taken from `.gitmodules`.
+#[derive(Clone, Debug)]
+pub struct Submodule {
+    pub path: PathBuf,
+    pub url: String,
+    pub commit: Oid,
+}
+
+impl GitSource {
+    /// Read the tree of `commit` in `repo`.
+    pub fn new(repo: Repository, commit: Oid) -> Result<GitSource> {
+        let tree = repo.find_commit(commit)?.tree_id();
+        Ok(GitSource {
+            repo: repo,
+            commit: commit,
+            tree: tree,
+            mounts: Vec::new(),
+        })
+    }
+
+    /// Open repository at `path`, and read the tree of `revision`, or HEAD if not given.
+    pub fn open(path: &Path, revision: Option<&Revision>) -> Result<GitSource> {
+        let repo = Repository::open(path)?;
+        let commit = match revision {
+            Some(rev) => git::resolve(&repo, rev)?,
+            None => git::head_commit(&repo)?,
+        };
+        GitSource::new(repo, commit)
+    }
+
+    /// Id of the commit files are read from.
+    pub fn commit(&self) -> Oid {
+        self.commit
+    }
+
+    /// Submodules in the tree, at any depth.
+    pub fn submodules(&self) -> Result<Vec<Submodule>> {
+        let mut links = Vec::new();
+        self.find_gitlinks(&self.tree()?, Path::new(""), &mut links)?;
+        if links.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // libgit2 reads config only from files, so `.gitmodules` is copied out of the tree
+        let content = self.read(Path::new(".gitmodules"))
+            .chain_err(|| "submodules found without `.gitmodules`")?;
+        let tmp = TempDir::new("rig__gitmodules")?;
+        let file = tmp.path().join("gitmodules");
+        fsutils::write_bytes(&file, &content)?;
+        let config = Config::open(&file)?;
+
+        let mut submodules = Vec::new();
+        for (path, commit) in links {
+            let url = gitmodules_url(&config, &path)?;
+            submodules.push(Submodule {
+                path: path,
+                url: url,
+                commit: commit,
+            });
+        }
+        Ok(submodules)
+    }
+
+    /// Read submodules from mirrors `fetch` returns, recursively,
+    /// so their files are part of the tree.
+    ///
+    /// Relative submodule URLs are resolved against `url`, which this repository was fetched from.
+    pub fn load_submodules<F>(&mut self, url: &Url, fetch: &F) -> Result<()>
+        where F: Fn(&Url) -> Result<PathBuf>
+    {
+        for submodule in self.submodules()? {
+            let sub_url = submodule_url(url, &submodule.url)?;
+            info!("Loading submodule {:?} from {}", submodule.path, sub_url);
+
+            let repo = Repository::open(fetch(&sub_url)?)?;
+            let mut source = GitSource::new(repo, submodule.commit)
+                .chain_err(|| {
+                    format!("commit {} of submodule {:?} not found in {}",
+                            submodule.commit,
+                            submodule.path,
+                            sub_url)
+                })?;
+            source.load_submodules(&sub_url, fetch)?;
+            self.mounts.push((submodule.path, source));
+        }
+        Ok(())
+    }
+
+    fn find_gitlinks(&self,
+                     tree: &Tree,
+                     dir: &Path,
+                     links: &mut Vec<(PathBuf, Oid)>)
+                     -> Result<()> {
+        for entry in tree.iter() {
+            let path = dir.join(entry.name().unwrap_or(""));
+            match entry.kind() {
+                Some(ObjectType::Tree) => {
+                    let subtree = self.repo.find_tree(entry.id())?;
+                    self.find_gitlinks(&subtree, &path, links)?;
+                }
+                Some(ObjectType::Commit) => links.push((path, entry.id())),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Loaded submodule containing `path`, and the path relative to it.
+    fn mount_for(&self, path: &Path) -> Option<(&GitSource, PathBuf)> {
+        self.mounts
+            .iter()
+            .find(|&&(ref mount, _)| path.starts_with(mount))
+            .map(|&(ref mount, ref source)| {
+                (source, path.strip_prefix(mount).unwrap().to_path_buf())
+            })
+    }
+
+    fn tree(&self) -> Result<Tree> {
+        self.repo.find_tree(self.tree).map_err(|e| e.into())
+    }
+
+    fn kind_of(&self, path: &Path) -> Option<ObjectType> {
+        if is_top(path) {
+            return Some(ObjectType::Tree);
+        }
+        self.tree()
+            .ok()
+            .and_then(|tree| tree.get_path(path).ok())
+            .and_then(|entry| entry.kind())
+    }
+
+    fn walk(&self, tree: &Tree, dir: &Path, entries: &mut Vec<Entry>) -> Result<()> {
+        for entry in tree.iter() {
+            let path = dir.join(entry.name().unwrap_or(""));
+            match entry.kind() {
+                Some(ObjectType::Tree) => {
+                    entries.push(Entry::dir(path.clone()));
+                    let subtree = self.repo.find_tree(entry.id())?;
+                    self.walk(&subtree, &path, entries)?;
+                }
+                Some(ObjectType::Blob) => entries.push(Entry::file(path)),
+                Some(ObjectType::Commit) => {
+                    entries.push(Entry::dir(path.clone()));
+                    match self.mount_for(&path) {
+                        Some((source, _)) => {
+                            for entry in source.entries(Path::new(""))? {
+                                entries.push(Entry {
+                                    path: path.join(entry.path),
+                                    kind: entry.kind,
+                                });
+                            }
+                        }
+                        None => debug!("Submodule {:?} is not loaded, leaving it empty", path),
+                    }
+                }
+                _ => debug!("Skipping {:?}, which is neither file nor directory", path),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Source for GitSource {
+    fn entries(&self, dir: &Path) -> Result<Vec<Entry>> {
+        if let Some((source, rel_dir)) = self.mount_for(dir) {
+            let prefix = dir.to_path_buf();
+            let mut entries = source.entries(&rel_dir)?;
+            for entry in &mut entries {
+                entry.path = prefix.join(entry.path.strip_prefix(&rel_dir).unwrap());
+            }
+            return Ok(entries);
+        }
+
+        let top = self.tree()?;
+        let tree = if is_top(dir) {
+            top
+        } else {
+            let id = top.get_path(dir)?.id();
+            self.repo.find_tree(id)?
+        };
+
+        let mut entries = Vec::new();
+        self.walk(&tree, dir, &mut entries)?;
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        if let Some((source, rel_path)) = self.mount_for(path) {
+            return source.read(&rel_path);
+        }
+        let id = self.tree()?.get_path(path)?.id();
+        let blob = self.repo.find_blob(id)?;
+        Ok(blob.content().to_vec())
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        match self.mount_for(path) {
+            Some((source, rel_path)) => source.is_dir(&rel_path),
+            None => {
+                let kind = self.kind_of(path);
+                kind == Some(ObjectType::Tree) || kind == Some(ObjectType::Commit)
+            }
+        }
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        match self.mount_for(path) {
+            Some((source, rel_path)) => source.is_file(&rel_path),
+            None => self.kind_of(path) == Some(ObjectType::Blob),
+        }
+    }
+}
+
+/// Template files held in memory.
+#[derive(Clone, Debug, Default)]
+pub struct MemorySource {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemorySource {
+    pub fn new() -> MemorySource {
+        MemorySource::default()
+    }
+
+    /// Add file, directories leading to it are created implicitly.
+    pub fn insert<P, C>(&mut self, path: P, content: C) -> &mut MemorySource
+        where P: Into<PathBuf>,
+              C: Into<Vec<u8>>
+    {
+        self.files.insert(path.into(), content.into());
+        self
+    }
+}
+
+impl Source for MemorySource {
+    fn entries(&self, dir: &Path) -> Result<Vec<Entry>> {
+        let mut entries: Vec<Entry> = Vec::new();
+        for path in self.files.keys().filter(|p| is_top(dir) || p.starts_with(dir)) {
+            let mut parent = PathBuf::new();
+            for component in path.parent().unwrap_or(Path::new("")).components() {
+                parent.push(component.as_os_str());
+                let entry = Entry::dir(parent.clone());
+                if parent.starts_with(dir) && parent != dir && !entries.contains(&entry) {
+                    entries.push(entry);
+                }
+            }
+            entries.push(Entry::file(path.clone()));
+        }
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.files
+            .get(path)
+            .cloned()
+            .ok_or(ErrorKind::TemplateNotFound(path.to_string_lossy().into_owned()).into())
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        is_top(path) || self.files.keys().any(|p| p.starts_with(path) && p != path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+}
+
+/// Find `submodule.<name>.url` of the submodule at `path`.
+fn gitmodules_url(config: &Config, path: &Path) -> Result<String> {
+    let entries = config.entries(Some(r"^submodule\..*\.path$"))?;
+    for entry in &entries {
+        let entry = entry?;
+        if entry.value().map(|v| Path::new(v.trim_right_matches('/')) == path).unwrap_or(false) {
+            let name = entry.name().unwrap_or("");
+            let key = format!("{}.url", name.trim_right_matches(".path"));
+            return config.get_string(&key).map_err(|e| e.into());
+        }
+    }
+    Err(format!("submodule {:?} is not listed in `.gitmodules`", path).into())
+}
+
+/// Resolve submodule URL, which may be relative to the URL of its superproject.
+pub fn submodule_url(base: &Url, raw: &str) -> Result<Url> {
+    if raw.starts_with("./") || raw.starts_with("../") {
+        // superproject URL is the directory relative URLs are resolved against
+        let base = Url::parse(&format!("{}/", base.as_str().trim_right_matches('/')))?;
+        return base.join(raw).map_err(|e| e.into());
+    }
+    if let Ok(url) = Url::parse(raw) {
+        return Ok(url);
+    }
+    if Path::new(raw).is_absolute() {
+        return Url::from_file_path(raw)
+            .map_err(|_| ErrorKind::InvalidUrlFormat(raw.to_string()).into());
+    }
+    Hosts::default().expand(raw)
+}
+
+fn is_top(path: &Path) -> bool {
+    path.components().all(|c| c == Component::CurDir)
+}
@@ -1,13 +1,36 @@
 use std::collections::HashMap;
 use std::convert::From;
+use std::fmt;
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::slice;
 
+use combine::ParseError;
+use serde::de::{Deserialize, Deserializer, MapVisitor, Visitor};
+use serde::de::impls::IgnoredAny;
+use serde_json::{Map, Value as JsonValue};
+use serde_json::value::to_value;
+use regex::Regex;
+use tera::{Context, Tera};
+use toml;
 use toml::value::{Table, Value};
 
+use super::builtins::NAMESPACE;
+use super::errors::{self, ErrorKind, RenderError, ResultExt};
+use super::filters;
 use super::format::{self, Formatter};
 use super::fsutils;
 use super::parser;
+use super::schema::ParamSpec;
+
+lazy_static! {
+    static ref TERA_TAG: Regex = Regex::new(r"(?s)\{[{%](.*?)[}%]\}").unwrap();
+    static ref TERA_LITERAL: Regex = Regex::new(r#""[^"]*"|'[^']*'"#).unwrap();
+    static ref TERA_IDENT: Regex = Regex::new(r"[A-Za-z_][A-Za-z0-9_]*").unwrap();
+    /// Operators, parentheses and parameter names in `$if$` conditions. Single `|` and `&`
+    /// are taken as tokens too, to be rejected.
+    static ref CONDITION_TOKEN: Regex = Regex::new(r"\|\||&&|[|&!()]|[^\s|&!()]+").unwrap();
+}
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Style {
@@ -67,11 +90,36 @@ impl Placeholder {
     }
 }
 
+/// Element of template following raw text.
+#[derive(Clone, Debug)]
+pub enum Token {
+    Placeholder(Placeholder),
+    /// `$if(cond)$`, with condition like `use_x.truthy`.
+    If(String),
+    ElseIf(String),
+    Else,
+    EndIf,
+}
+
+/// State of a conditional block being written.
+struct Block {
+    /// Whether the block itself is written, by enclosing blocks.
+    parent: bool,
+    /// Whether any branch of the block has been taken.
+    taken: bool,
+    /// Whether the current branch is written.
+    active: bool,
+    /// Where `$if$` of the block is in template, to tell when it is not closed.
+    offset: usize,
+}
+
 /// Minimal template for any kind of plain text.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Template {
     pub style: Style,
     pub body: String,
+    /// Where the template is read from, to tell in errors.
+    pub path: Option<PathBuf>,
 }
 
 impl Template {
@@ -80,12 +128,15 @@ impl Template {
         Template {
             style: style,
             body: String::from(template.as_ref()),
+            path: None,
         }
     }
 
     /// Create `Template` from contents of the file at given `Path`.
     pub fn read_file<P: AsRef<Path>>(style: Style, src: P) -> Result<Template, io::Error> {
-        fsutils::read_file(src.as_ref()).map(|s| Template::read_str(style, s))
+        fsutils::read_file(src.as_ref()).map(|s| {
+            Template { path: Some(src.as_ref().to_path_buf()), ..Template::read_str(style, s) }
+        })
     }
 
     /// Utility to create giter8 style template instantly.
@@ -94,41 +145,139 @@ impl Template {
     }
 
     /// Process template with given `params`, and write result into `writer`.
+    ///
+    /// Conditional blocks are written as StringTemplate does: a directive alone on its line
+    /// leaves no blank line behind.
     pub fn write_to<'a, W: Write>(&mut self,
                                  writer: &'a mut W,
                                  params: &HashMap<String, String>)
-                                 -> Result<&'a mut W, io::Error> {
+                                 -> errors::Result<&'a mut W> {
 
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut line_start = true;
         let mut progress = parser::parse_template(self.body.as_ref(), &self.style);
-        while let Ok((raw, maybe_ph, rest)) = progress {
-
-            if !raw.is_empty() {
-                writer.write(raw.as_bytes()).unwrap();
-            }
+        loop {
+            let (raw, maybe_token, rest) = match progress {
+                Ok(parsed) => parsed,
+                Err(e) => return Err(self.parse_error(e).into()),
+            };
+            let active = blocks.last().map(|b| b.active).unwrap_or(true);
+            let token_end = self.body.len() - rest.len();
+            let mut raw = raw.as_str();
+            let mut rest = rest;
 
-            if let Some(ph) = maybe_ph {
-                let value = ph.format_with(&params);
-                writer.write(value.as_bytes()).unwrap();
+            match maybe_token {
+                Some(Token::Placeholder(ph)) => {
+                    if active {
+                        writer.write_all(raw.as_bytes())?;
+                        let value = ph.format_with(&params);
+                        writer.write_all(value.as_bytes())?;
+                    }
+                    line_start = false;
+                }
+                Some(control) => {
+                    let tail = raw.rfind('\n').map(|i| i + 1).unwrap_or(0);
+                    let after = rest.trim_left_matches(|c| c == ' ' || c == '\t');
+                    let standalone = (tail > 0 || line_start) && raw[tail..].trim().is_empty() &&
+                                     (after.is_empty() || after.starts_with('\n') ||
+                                      after.starts_with("\r\n"));
+                    if standalone {
+                        raw = &raw[..tail];
+                        rest = &after[after.find('\n').map(|i| i + 1).unwrap_or(0)..];
+                    }
+                    if active {
+                        writer.write_all(raw.as_bytes())?;
+                    }
+                    let offset = self.body[..token_end - 1].rfind('$').unwrap_or(0);
+                    if let Err(reason) = update_blocks(&mut blocks, control, params, offset) {
+                        return Err(self.render_error(offset, reason).into());
+                    }
+                    line_start = standalone;
+                }
+                None => {
+                    if active {
+                        writer.write_all(raw.as_bytes())?;
+                    }
+                    line_start = raw.ends_with('\n') || (raw.is_empty() && line_start);
+                }
             }
 
             if rest.is_empty() {
-                writer.flush().unwrap();
                 break;
             } else {
                 progress = parser::parse_template(rest, &self.style);
             }
         }
-        writer.flush().unwrap();
+        if let Some(block) = blocks.last() {
+            let reason = "`$if$` without `$endif$`".to_string();
+            return Err(self.render_error(block.offset, reason).into());
+        }
+        writer.flush()?;
 
         Ok(writer)
     }
 
+    /// Locate where parsing failed, in the whole body.
+    fn parse_error<'a>(&'a self, err: ParseError<&'a str>) -> ErrorKind {
+        let err = err.translate_position(&self.body);
+        let reason = err.errors
+            .iter()
+            .map(|e| e.to_string().trim().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.render_error(err.position, reason)
+    }
+
+    /// Error at `offset` in the body, for `reason`.
+    fn render_error(&self, offset: usize, reason: String) -> ErrorKind {
+        let path = self.path.clone().unwrap_or(PathBuf::new());
+        ErrorKind::RenderFailure(RenderError::at_offset(path, &self.body, offset, reason))
+    }
+
+    /// Whether the body has any placeholder, or any tag for Tera.
+    pub fn has_placeholders(&self) -> bool {
+        match self.style {
+            Style::Tera => self.body.contains("{{") || self.body.contains("{%"),
+            _ => !self.references().is_empty(),
+        }
+    }
+
+    /// Names the template refers to. For Tera, these are all identifiers in tags,
+    /// including keywords and filters, so they should be looked up in known names.
+    pub fn references(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        match self.style {
+            Style::Tera => {
+                for tag in TERA_TAG.captures_iter(&self.body) {
+                    let expr = TERA_LITERAL.replace_all(&tag[1], "");
+                    names.extend(TERA_IDENT.find_iter(&expr).map(|m| m.as_str().to_string()));
+                }
+            }
+            _ => {
+                let mut progress = parser::parse_template(self.body.as_ref(), &self.style);
+                while let Ok((_, maybe_token, rest)) = progress {
+                    match maybe_token {
+                        Some(Token::Placeholder(ph)) => names.push(ph.name),
+                        Some(Token::If(ref cond)) |
+                        Some(Token::ElseIf(ref cond)) => names.extend(condition_names(cond)),
+                        _ => {}
+                    }
+                    if rest.is_empty() {
+                        break;
+                    }
+                    progress = parser::parse_template(rest, &self.style);
+                }
+            }
+        }
+        names
+    }
+
     /// Create template from given `str`, and instantly write it.
     pub fn write_once<'a, S, W>(writer: &'a mut W,
                                 style: Style,
                                 template: S,
                                 params: &HashMap<String, String>)
-                                -> Result<&'a mut W, io::Error>
+                                -> errors::Result<&'a mut W>
         where S: AsRef<str>,
               W: Write
     {
@@ -137,48 +286,532 @@ impl Template {
     }
 }
 
+/// Apply conditional directive at `offset` to the stack of blocks.
+fn update_blocks(blocks: &mut Vec<Block>,
+                 token: Token,
+                 params: &HashMap<String, String>,
+                 offset: usize)
+                 -> Result<(), String> {
+    match token {
+        Token::If(ref cond) => {
+            let parent = blocks.last().map(|b| b.active).unwrap_or(true);
+            let active = eval_condition(cond, params)? && parent;
+            blocks.push(Block {
+                parent: parent,
+                taken: active,
+                active: active,
+                offset: offset,
+            });
+        }
+        Token::ElseIf(ref cond) => {
+            let holds = eval_condition(cond, params)?;
+            let block = blocks.last_mut().ok_or("`$elseif$` without `$if$`".to_string())?;
+            block.active = block.parent && !block.taken && holds;
+            block.taken = block.taken || block.active;
+        }
+        Token::Else => {
+            let block = blocks.last_mut().ok_or("`$else$` without `$if$`".to_string())?;
+            block.active = block.parent && !block.taken;
+            block.taken = true;
+        }
+        Token::EndIf => {
+            blocks.pop().ok_or("`$endif$` without `$if$`".to_string())?;
+        }
+        Token::Placeholder(_) => {}
+    }
+    Ok(())
+}
+
+/// Evaluate condition of `$if$`, like `use_x.truthy`, `!use_x` or `(a || b.truthy) && c`.
+/// `!` binds tighter than `&&`, which binds tighter than `||`.
+///
+/// `name.truthy` holds if the value is `y`, `yes` or `true` ignoring case, as in giter8.
+/// `name` alone holds if the parameter exists, as in StringTemplate.
+pub fn eval_condition(cond: &str, params: &HashMap<String, String>) -> Result<bool, String> {
+    let tokens = CONDITION_TOKEN.find_iter(cond).map(|m| m.as_str()).collect::<Vec<_>>();
+    let mut pos = 0;
+    let holds = eval_or(&tokens, &mut pos, params).and_then(|holds| {
+        match tokens.get(pos) {
+            Some(token) => Err(format!("unexpected `{}`", token)),
+            None => Ok(holds),
+        }
+    });
+    holds.map_err(|reason| format!("invalid condition `{}`: {}", cond, reason))
+}
+
+fn eval_or(tokens: &[&str], pos: &mut usize, params: &HashMap<String, String>)
+    -> Result<bool, String>
+{
+    let mut holds = eval_and(tokens, pos, params)?;
+    while tokens.get(*pos) == Some(&"||") {
+        *pos += 1;
+        // right side is always read, to move past it
+        let rhs = eval_and(tokens, pos, params)?;
+        holds = holds || rhs;
+    }
+    Ok(holds)
+}
+
+fn eval_and(tokens: &[&str], pos: &mut usize, params: &HashMap<String, String>)
+    -> Result<bool, String>
+{
+    let mut holds = eval_term(tokens, pos, params)?;
+    while tokens.get(*pos) == Some(&"&&") {
+        *pos += 1;
+        let rhs = eval_term(tokens, pos, params)?;
+        holds = holds && rhs;
+    }
+    Ok(holds)
+}
+
+fn eval_term(tokens: &[&str], pos: &mut usize, params: &HashMap<String, String>)
+    -> Result<bool, String>
+{
+    let token = match tokens.get(*pos) {
+        Some(token) => *token,
+        None => return Err("a parameter is expected".to_string()),
+    };
+    *pos += 1;
+    match token {
+        "!" => eval_term(tokens, pos, params).map(|holds| !holds),
+        "(" => {
+            let holds = eval_or(tokens, pos, params)?;
+            if tokens.get(*pos) != Some(&")") {
+                return Err("`(` is not closed".to_string());
+            }
+            *pos += 1;
+            Ok(holds)
+        }
+        "||" | "&&" | "|" | "&" | ")" => Err(format!("unexpected `{}`", token)),
+        name if name.ends_with(".truthy") => {
+            let holds = params.get(&name[..name.len() - ".truthy".len()])
+                .map(|v| {
+                    match v.to_lowercase().as_ref() {
+                        "y" | "yes" | "true" => true,
+                        _ => false,
+                    }
+                })
+                .unwrap_or(false);
+            Ok(holds)
+        }
+        name => Ok(params.contains_key(name)),
+    }
+}
+
+/// Names of parameters condition refers to.
+fn condition_names(cond: &str) -> Vec<String> {
+    cond.split(|c| c == '|' || c == '&' || c == '!' || c == '(' || c == ')')
+        .map(|term| term.trim().trim_right_matches(".truthy"))
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
 /// Wrapper arround map-type collection to use as resolved parameters in project generation.
+///
+/// Parameters keep the order they are declared in, which `keys` and `iter` follow.
+/// Each value is held with its TOML type in `values`, and as string rendering in `param_map`
+/// for templates which only deal with strings. Parameters declared in `[params.<name>]`
+/// tables have their prompt and validation rules in `specs`.
+///
+/// Defaults written as templates, like `com.example.$name$`, are kept in `computed`
+/// until `compute` evaluates them, or they are given values.
+///
+/// Built-in variables are in `builtins`, which templates see as `rig`, not as parameters.
 #[derive(Debug, Clone)]
 pub struct Params {
     pub param_map: HashMap<String, String>,
+    pub values: HashMap<String, Value>,
+    pub keys: Vec<String>,
+    pub specs: HashMap<String, ParamSpec>,
+    pub computed: HashMap<String, Template>,
+    pub builtins: Table,
     pub toml: Option<Table>,
 }
 
 impl Params {
 
     pub fn minimal_req() -> Params {
-        let mut minimal = HashMap::new();
-        minimal.insert("name".into(), "Project Generated By Rig".into());
-        Params::from_map(minimal)
+        Params::from_pairs(vec![("name".into(), "Project Generated By Rig".into())])
     }
 
+    /// Make params from map, which has no order; keys are sorted alphabetically.
     pub fn from_map(map: HashMap<String, String>) -> Params {
-        Params { param_map: map, toml: None }
+        let mut pairs = map.into_iter().collect::<Vec<_>>();
+        pairs.sort();
+        Params::from_pairs(pairs)
+    }
+
+    /// Make params from key-value pairs in declaration order.
+    pub fn from_pairs<I>(pairs: I) -> Params
+        where I: IntoIterator<Item = (String, String)>
+    {
+        let mut params = Params::empty();
+        for (k, v) in pairs {
+            params.insert_value(k, Value::String(v));
+        }
+        params
+    }
+
+    fn empty() -> Params {
+        Params {
+            param_map: HashMap::new(),
+            values: HashMap::new(),
+            keys: Vec::new(),
+            specs: HashMap::new(),
+            computed: HashMap::new(),
+            builtins: Table::new(),
+            toml: None,
+        }
+    }
+
+    /// Convert top level values of `toml`. `Table` does not remember declaration order,
+    /// so keys are sorted alphabetically; use `read_toml` to keep the order.
+    pub fn convert_toml(toml: Table) -> errors::Result<Params> {
+        let keys = toml.keys().cloned().collect::<Vec<_>>();
+        let spec_keys = match toml.get("params") {
+            Some(&Value::Table(ref table)) => table.keys().cloned().collect(),
+            _ => Vec::new(),
+        };
+        Params::convert_toml_in_order(toml, &keys, &spec_keys)
     }
 
-    pub fn convert_toml(toml: Table) -> Params {
-        let mut raw_values = HashMap::new();
-        for (k, tv) in &toml {
-            if let Some(v) = convert(&tv) {
-                raw_values.insert(k.clone(), v);
+    /// Parse TOML document, keeping top level values in the order they are declared.
+    pub fn read_toml(content: &str) -> errors::Result<Params> {
+        let toml: Table = toml::from_str(content).chain_err(|| ErrorKind::TomlDecodeFailure)?;
+        let keys: TableKeys = toml::from_str(content).chain_err(|| ErrorKind::TomlDecodeFailure)?;
+        Params::convert_toml_in_order(toml, &keys.keys, &keys.params)
+    }
+
+    /// Top level values become parameters, except `params` table which declares
+    /// parameters with prompts and validation rules, in `spec_keys` order.
+    fn convert_toml_in_order(mut toml: Table,
+                             keys: &[String],
+                             spec_keys: &[String])
+                             -> errors::Result<Params> {
+        let schema = match toml.remove("params") {
+            Some(Value::Table(table)) => Some(table),
+            Some(other) => {
+                toml.insert("params".into(), other);
+                None
+            }
+            None => None,
+        };
+
+        let mut params = Params::empty();
+        for k in keys {
+            if let Some(v) = toml.get(k) {
+                params.insert_value(k.clone(), v.clone());
+            }
+        }
+
+        if let Some(schema) = schema {
+            for name in spec_keys {
+                let spec = match schema.get(name) {
+                    Some(&Value::Table(ref table)) => ParamSpec::from_toml(name, table)?,
+                    Some(_) => {
+                        let reason = "must be a table like `[params.<name>]`".into();
+                        return Err(ErrorKind::InvalidParam(name.clone(), reason).into());
+                    }
+                    None => continue,
+                };
+                // value declared at top level takes place of `default`
+                match toml.get(name) {
+                    Some(value) => {
+                        spec.validate(value)
+                            .map_err(|reason| ErrorKind::InvalidParam(name.clone(), reason))?
+                    }
+                    None => params.insert_value(name.clone(), spec.fallback()),
+                }
+                params.specs.insert(name.clone(), spec);
+            }
+        }
+        params.toml = Some(toml);
+        Ok(params)
+    }
+
+    /// Take string values written as templates of `style` as computed defaults.
+    pub fn read_computed(&mut self, style: Style) {
+        for key in &self.keys {
+            if let Some(&Value::String(ref s)) = self.values.get(key) {
+                let template = Template::read_str(style.clone(), s);
+                if template.has_placeholders() {
+                    self.computed.insert(key.clone(), template);
+                }
             }
         }
-        Params { param_map: raw_values, toml: Some(toml) }
+    }
+
+    /// Evaluate computed default of `key` with current values, evaluating computed defaults
+    /// it refers to first. Nothing is done if `key` already has a value given.
+    pub fn compute(&mut self, key: &str) -> errors::Result<()> {
+        self.compute_in(key, &mut Vec::new())
+    }
+
+    /// Evaluate all computed defaults left, in declaration order.
+    pub fn compute_all(&mut self) -> errors::Result<()> {
+        for key in self.keys.clone() {
+            self.compute(&key)?;
+        }
+        Ok(())
+    }
+
+    fn compute_in(&mut self, key: &str, stack: &mut Vec<String>) -> errors::Result<()> {
+        let template = match self.computed.get(key) {
+            Some(template) => template.clone(),
+            None => return Ok(()),
+        };
+        stack.push(key.to_string());
+        if stack[..stack.len() - 1].iter().any(|k| k == key) {
+            let reason = format!("default refers to itself: {}", stack.join(" -> "));
+            return Err(ErrorKind::InvalidParam(stack[0].clone(), reason).into());
+        }
+
+        for name in template.references() {
+            if self.values.contains_key(&name) {
+                self.compute_in(&name, stack)?;
+            }
+        }
+        stack.pop();
+
+        let value = self.render_default(key, &template)?;
+        self.insert(key.to_string(), value)
+    }
+
+    fn render_default(&self, key: &str, template: &Template) -> errors::Result<String> {
+        match template.style {
+            Style::Tera => self.render_tera(key, "default", &template.body),
+            ref style => {
+                let mut buf = Vec::new();
+                Template::write_once(&mut buf, style.clone(), &template.body, &self.param_map)?;
+                Ok(String::from_utf8_lossy(&buf).into_owned())
+            }
+        }
+    }
+
+    /// Whether `key` is to be asked, by its `when` condition over current values.
+    pub fn is_asked(&self, key: &str) -> errors::Result<bool> {
+        match self.specs.get(key).and_then(|spec| spec.when.as_ref()) {
+            Some(condition) => {
+                let body = format!("{{% if {} %}}true{{% endif %}}", condition);
+                self.render_tera(key, "when", &body).map(|result| result == "true")
+            }
+            None => Ok(true),
+        }
+    }
+
+    /// Render Tera template of parameter `key`, which is `what` of the parameter for errors.
+    fn render_tera(&self, key: &str, what: &str, body: &str) -> errors::Result<String> {
+        let mut tera = Tera::default();
+        filters::register(&mut tera);
+        tera.add_raw_template(key, body)
+            .and_then(|_| tera.render(key, self.tera_context()))
+            .map_err(|e| {
+                let causes = e.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+                let reason = format!("{}: {}", what, causes.join(": "));
+                ErrorKind::InvalidParam(key.to_string(), reason).into()
+            })
+    }
+
+    /// Set built-in variables, which are `rig.year` and so on in templates.
+    pub fn set_builtins(&mut self, builtins: Table) {
+        for k in self.builtins.keys() {
+            self.param_map.remove(&format!("{}.{}", NAMESPACE, k));
+        }
+        for (k, v) in &builtins {
+            self.param_map.insert(format!("{}.{}", NAMESPACE, k), render(v));
+        }
+        self.builtins = builtins;
+    }
+
+    /// Context for Tera, where values are given with their own types,
+    /// so templates can test booleans or loop over arrays.
+    pub fn tera_context(&self) -> Context {
+        let mut ctx = Context::new();
+        for k in &self.keys {
+            if let Some(v) = self.values.get(k) {
+                ctx.add(k, &to_json(v));
+            }
+        }
+        if !self.builtins.is_empty() {
+            ctx.add(NAMESPACE, &to_json(&Value::Table(self.builtins.clone())));
+        }
+        ctx
     }
 
     pub fn get(&self, key: &str) -> Option<&String> {
         self.param_map.get(key)
     }
+
+    pub fn get_value(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    /// Set value of `key` from user input, which is appended to the last if it is new.
+    ///
+    /// Input is read as the type of declared value if it can be, like `true` for boolean,
+    /// or `a, b` and `["a", "b"]` for array. Otherwise it is kept as string.
+    /// Parameters declared in `[params.<name>]` only take values their declaration allows.
+    pub fn insert(&mut self, key: String, value: String) -> errors::Result<()> {
+        if let Some(spec) = self.specs.get(&key).cloned() {
+            let typed = spec.read_input(&value)
+                .map_err(|reason| ErrorKind::InvalidParam(key.clone(), reason))?;
+            self.insert_value(key, typed);
+            return Ok(());
+        }
+        let declared = self.toml.as_ref().and_then(|t| t.get(&key)).or(self.values.get(&key));
+        let typed = match declared {
+            Some(declared) => {
+                parse_as(declared, &value)
+                    .map_err(|reason| ErrorKind::InvalidParam(key.clone(), reason))?
+            }
+            None => Value::String(value),
+        };
+        self.insert_value(key, typed);
+        Ok(())
+    }
+
+    /// Set typed value of `key`, which is appended to the last if it is new.
+    pub fn insert_value(&mut self, key: String, value: Value) {
+        self.computed.remove(&key);
+        if !self.values.contains_key(&key) {
+            self.keys.push(key.clone());
+        }
+        self.param_map.insert(key.clone(), render(&value));
+        self.values.insert(key, value);
+    }
+
+    /// Iterate over parameters in declaration order.
+    pub fn iter(&self) -> Iter {
+        Iter { params: self, keys: self.keys.iter() }
+    }
 }
 
-// FIXME: should return `Result<String, errors::Error>` to tell we won't accept table / array?
-fn convert(value: &Value) -> Option<String> {
+/// Iterator over parameters in declaration order, created by `Params::iter`.
+pub struct Iter<'a> {
+    params: &'a Params,
+    keys: slice::Iter<'a, String>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a String, &'a String);
+
+    fn next(&mut self) -> Option<(&'a String, &'a String)> {
+        while let Some(key) = self.keys.next() {
+            if let Some(value) = self.params.param_map.get(key) {
+                return Some((key, value));
+            }
+        }
+        None
+    }
+}
+
+/// Keys of TOML table in document order, as `Table` sorts them.
+/// Keys of `params` table are kept too, for the order of declared parameters.
+struct TableKeys {
+    keys: Vec<String>,
+    params: Vec<String>,
+}
+
+impl Deserialize for TableKeys {
+    fn deserialize<D: Deserializer>(deserializer: D) -> Result<TableKeys, D::Error> {
+        struct KeysVisitor;
+
+        impl Visitor for KeysVisitor {
+            type Value = TableKeys;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a table")
+            }
+
+            fn visit_map<V: MapVisitor>(self, mut visitor: V) -> Result<TableKeys, V::Error> {
+                let mut keys = Vec::new();
+                let mut params = Vec::new();
+                while let Some(key) = visitor.visit_key::<String>()? {
+                    if key == "params" {
+                        params = visitor.visit_value::<TableKeys>()?.keys;
+                    } else {
+                        visitor.visit_value::<IgnoredAny>()?;
+                    }
+                    keys.push(key);
+                }
+                Ok(TableKeys {
+                    keys: keys,
+                    params: params,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(KeysVisitor)
+    }
+}
+
+/// Render value as string, for templates which do not know types.
+/// Arrays are joined with `,`, and tables are written in TOML.
+pub fn render(value: &Value) -> String {
     match *value {
-        Value::String(_) => value.as_str().map(|s| s.to_owned()),
-        Value::Datetime(_) => value.as_datetime().map(|s| s.to_string()),
-        Value::Integer(_) => value.as_integer().map(|i| i.to_string()),
-        Value::Float(_) => value.as_float().map(|f| f.to_string()),
-        Value::Boolean(_) => value.as_bool().map(|b| b.to_string()),
-        _ => None,
+        Value::String(ref s) => s.clone(),
+        Value::Datetime(ref d) => d.to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Array(ref items) => items.iter().map(render).collect::<Vec<_>>().join(","),
+        Value::Table(ref table) => toml::to_string(table).unwrap_or(String::new()),
     }
 }
+
+/// Convert TOML value into JSON value Tera works on. Datetime becomes string.
+fn to_json(value: &Value) -> JsonValue {
+    match *value {
+        Value::String(ref s) => JsonValue::String(s.clone()),
+        Value::Datetime(ref d) => JsonValue::String(d.to_string()),
+        Value::Integer(i) => to_value(i).unwrap_or(JsonValue::Null),
+        Value::Float(f) => to_value(f).unwrap_or(JsonValue::Null),
+        Value::Boolean(b) => JsonValue::Bool(b),
+        Value::Array(ref items) => JsonValue::Array(items.iter().map(to_json).collect()),
+        Value::Table(ref table) => {
+            let mut object = Map::new();
+            for (k, v) in table {
+                object.insert(k.clone(), to_json(v));
+            }
+            JsonValue::Object(object)
+        }
+    }
+}
+
+/// Read `raw` as the type of `current`, or explain why it is not of that type.
+fn parse_as(current: &Value, raw: &str) -> ::std::result::Result<Value, String> {
+    let raw = raw.trim();
+    let parsed = match *current {
+        Value::String(_) => None,
+        Value::Boolean(_) => {
+            match raw.to_lowercase().as_ref() {
+                "true" | "yes" | "y" => Some(Value::Boolean(true)),
+                "false" | "no" | "n" => Some(Value::Boolean(false)),
+                _ => None,
+            }
+        }
+        Value::Array(_) if !raw.starts_with('[') => {
+            let items = raw.split(',')
+                .map(|item| item.trim())
+                .filter(|item| !item.is_empty())
+                .map(|item| parse_toml(item).unwrap_or(Value::String(item.to_string())))
+                .collect();
+            Some(Value::Array(items))
+        }
+        _ => parse_toml(raw),
+    };
+
+    match parsed {
+        Some(ref value) if value.same_type(current) => Ok(value.clone()),
+        None if current.is_str() => Ok(Value::String(raw.to_string())),
+        _ => Err(format!("`{}` is not {}", raw, current.type_str())),
+    }
+}
+
+/// Parse `raw` as TOML value, like `42` or `["a", "b"]`.
+fn parse_toml(raw: &str) -> Option<Value> {
+    toml::from_str::<Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+}
@@ -1,5 +1,33 @@
 extern crate rig;
 
+mod support {
+
+    extern crate git2;
+
+    use std::path::Path;
+
+    use rig::fsutils;
+    use self::git2::{Oid, Repository, Signature};
+
+    pub fn commit_file(repo: &Repository, name: &str, content: &str) -> Oid {
+        let workdir = repo.workdir().unwrap().to_path_buf();
+        fsutils::write_file(&workdir.join(name), content).unwrap();
+
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+
+        let sig = Signature::now("rig", "rig@example.com").unwrap();
+        let parents = match repo.head() {
+            Ok(head) => vec![repo.find_commit(head.target().unwrap()).unwrap()],
+            Err(_) => vec![],
+        };
+        let parents = parents.iter().collect::<Vec<_>>();
+        repo.commit(Some("HEAD"), &sig, &sig, "update", &tree, &parents).unwrap()
+    }
+}
+
 mod format_test {
 
     use std::ascii::AsciiExt;
@@ -153,6 +181,165 @@ mod template_test {
         assert_eq!(str::from_utf8(&out).unwrap(),
                    "trait AwesomeDistributedInterface[-A] extends js.Dictionary[A]".to_owned());
     }
+
+    #[test]
+    fn giter8_conditionals() {
+        let mut params: HashMap<String, String> = HashMap::new();
+        params.insert("name".to_owned(), "app".to_owned());
+        params.insert("docker".to_owned(), "yes".to_owned());
+        params.insert("ci".to_owned(), "false".to_owned());
+        params.insert("db".to_owned(), "mysql".to_owned());
+
+        let body = r#"name: $name$
+$if(docker.truthy)$
+docker:
+  $if(ci.truthy)$
+  ci: true
+  $else$
+  ci: false
+  $endif$
+$endif$
+$if(!ci.truthy && name)$local$else$remote$endif$
+"#;
+        let mut out = Vec::new();
+        Template::write_once(&mut out, Style::ST, body, &params).unwrap();
+        assert_eq!(str::from_utf8(&out).unwrap(),
+                   "name: app\ndocker:\n  ci: false\nlocal\n");
+
+        let body = "$if(postgres.truthy)$pg$elseif(db)$$db$$else$none$endif$";
+        let mut out = Vec::new();
+        Template::write_once(&mut out, Style::ST, body, &params).unwrap();
+        assert_eq!(str::from_utf8(&out).unwrap(), "mysql");
+
+        params.insert("docker".to_owned(), "No".to_owned());
+        let body = "a\n$if(docker.truthy)$\nb\n$elseif(ci.truthy)$\nc\n$endif$\nd";
+        let mut out = Vec::new();
+        Template::write_once(&mut out, Style::ST, body, &params).unwrap();
+        assert_eq!(str::from_utf8(&out).unwrap(), "a\nd");
+    }
+
+    #[test]
+    fn truthy_values() {
+        let mut params: HashMap<String, String> = HashMap::new();
+        for value in &["y", "YES", "True"] {
+            params.insert("x".to_owned(), value.to_string());
+            assert!(eval_condition("x.truthy", &params).unwrap());
+        }
+        for value in &["n", "no", "false", "1", ""] {
+            params.insert("x".to_owned(), value.to_string());
+            assert!(!eval_condition("x.truthy", &params).unwrap());
+        }
+        assert!(eval_condition("x", &params).unwrap());
+        assert!(!eval_condition("y.truthy", &params).unwrap());
+        assert!(eval_condition("!y || y", &params).unwrap());
+
+        // `!` binds tighter than `&&`, and `&&` tighter than `||`
+        params.insert("a".to_owned(), "yes".to_owned());
+        assert!(eval_condition("a.truthy || y && x", &params).unwrap());
+        assert!(!eval_condition("(a.truthy || x) && y", &params).unwrap());
+        assert!(eval_condition("!(y && x) && !y", &params).unwrap());
+        assert!(!eval_condition("!(a.truthy || y)", &params).unwrap());
+
+        let body = "$if((a.truthy || y) && x)$yes$endif$$if(!(a && x))$no$endif$";
+        let mut out = Vec::new();
+        Template::write_once(&mut out, Style::ST, body, &params).unwrap();
+        assert_eq!(str::from_utf8(&out).unwrap(), "yes");
+    }
+
+    #[test]
+    fn malformed_conditions() {
+        let params: HashMap<String, String> = HashMap::new();
+        for cond in &["", "a & b", "a | b", "a &&", "|| a", "(a || b", "a)", "!"] {
+            assert!(eval_condition(cond, &params).is_err(), "{}", cond);
+        }
+
+        let render = |body: &str| {
+            let mut out = Vec::new();
+            Template::write_once(&mut out, Style::ST, body, &params).map(|_| ())
+        };
+        for body in &["$if()$a$endif$", "a\n$if(x & y)$b$endif$", "$if((x)$a$endif$"] {
+            assert!(render(body).is_err(), "{}", body);
+        }
+
+        let message = render("a\nb $if(x)$\nc").unwrap_err().to_string();
+        assert!(message.contains("line 2, column 3"), "{}", message);
+        assert!(message.contains("`$if$` without `$endif$`"), "{}", message);
+        for body in &["a $else$", "$elseif(x)$", "a\n$endif$"] {
+            assert!(render(body).is_err(), "{}", body);
+        }
+        let message = render("a\n  $endif$").unwrap_err().to_string();
+        assert!(message.contains("line 2, column 3"), "{}", message);
+    }
+
+    #[test]
+    fn declaration_order() {
+        let params = Params::read_toml(r#"
+            name = "ordered"
+            package = "com.example"
+            author = "me"
+            version = 1
+            modules = [1, 2]
+
+            [license]
+            name = "MIT"
+        "#).unwrap();
+        assert_eq!(params.keys, vec!["name", "package", "author", "version", "modules", "license"]);
+        assert_eq!(params.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(),
+                   vec!["name", "package", "author", "version", "modules", "license"]);
+
+        let mut params = Params::from_pairs(vec![("zeta".to_owned(), "1".to_owned()),
+                                                 ("alpha".to_owned(), "2".to_owned())]);
+        params.insert("zeta".to_owned(), "3".to_owned()).unwrap();
+        params.insert("beta".to_owned(), "4".to_owned()).unwrap();
+        assert_eq!(params.iter().collect::<Vec<_>>(),
+                   vec![(&"zeta".to_owned(), &"3".to_owned()),
+                        (&"alpha".to_owned(), &"2".to_owned()),
+                        (&"beta".to_owned(), &"4".to_owned())]);
+    }
+
+    #[test]
+    fn computed_defaults() {
+        let mut params = Params::from_pairs(vec![
+            ("package".to_owned(), "com.example.$name;format=\"norm,word\"$".to_owned()),
+            ("name".to_owned(), "My App".to_owned()),
+            ("class".to_owned(), "$name;format=\"Camel\"$Main".to_owned()),
+        ]);
+        params.read_computed(Style::ST);
+        assert_eq!(params.computed.len(), 2);
+
+        params.insert("name".to_owned(), "Hello World".to_owned()).unwrap();
+        params.compute("class").unwrap();
+        assert_eq!(params.get("class").unwrap(), "HelloWorldMain");
+        params.insert("class".to_owned(), "Given".to_owned()).unwrap();
+        params.compute_all().unwrap();
+        assert_eq!(params.get("package").unwrap(), "com.example.helloworld");
+        assert_eq!(params.get("class").unwrap(), "Given");
+        assert!(params.computed.is_empty());
+
+        let mut params = Params::read_toml(r#"
+            crate_name = "{{ name | snake | lower }}"
+            name = "My App"
+            binary = "{% if cli %}{{ crate_name }}-cli{% else %}none{% endif %}"
+            cli = true
+        "#).unwrap();
+        params.read_computed(Style::Tera);
+        params.compute_all().unwrap();
+        assert_eq!(params.get("crate_name").unwrap(), "my_app");
+        assert_eq!(params.get("binary").unwrap(), "my_app-cli");
+
+        let mut params = Params::read_toml(r#"
+            a = "{{ b }}"
+            b = "x-{{ c }}"
+            c = "{{ a | upper }}"
+            d = "{% if e == \"a\" %}yes{% endif %}"
+            e = "a"
+        "#).unwrap();
+        params.read_computed(Style::Tera);
+        params.compute("d").unwrap();
+        assert_eq!(params.get("d").unwrap(), "yes");
+        let err = params.compute_all().unwrap_err().to_string();
+        assert!(err.contains("a -> b -> c -> a"), "{}", err);
+    }
 }
 
 mod project_test {
@@ -160,8 +347,11 @@ mod project_test {
     extern crate tempdir;
     use std::fs;
 
+    use rig::builtins::{self, Builtins};
+    use rig::errors::ErrorKind;
     use rig::fsutils;
     use rig::project::{Configuration, Project};
+    use rig::source::MemorySource;
 
     const G8_PROPS: &'static str = r#"
         name = value1
@@ -203,7 +393,7 @@ mod project_test {
         let params = project.default_params(&src).unwrap();
         assert_eq!(params.get("name"), Some(&"My Project".to_owned()));
         assert_eq!(params.get("module_name"), Some(&"quux".to_owned()));
-        assert!(params.get("will_be_ignored").is_none());
+        assert_eq!(params.get("will_be_ignored"), Some(&"4,5,6,7".to_owned()));
 
         project.generate(&params, &src, &dest, false).unwrap();
 
@@ -246,6 +436,7 @@ mod project_test {
 
         let params = project.default_params(&src).unwrap();
         assert_eq!(params.get("name"), Some(&"value1".to_owned()));
+        assert_eq!(params.keys, vec!["name", "bar", "package"]);
 
         project.generate(&params, &src, &dest, false).unwrap();
 
@@ -261,4 +452,1245 @@ mod project_test {
             assert!(fsutils::exists(&goal));
         }
     }
+
+    #[test]
+    fn typed_params() {
+        let mut source = MemorySource::new();
+        source.insert("Rig.toml", r#"
+                name = "typed"
+                use_docker = false
+                port = 8080
+                modules = ["core", "web"]
+
+                [owner]
+                name = "me"
+            "#)
+            .insert("README.md",
+                    "{% if use_docker %}docker{% else %}bare{% endif %} {{ port + 1 }} \
+                     {% for m in modules %}[{{ m }}]{% endfor %} {{ owner.name }}");
+
+        let project = Project::default();
+        let mut params = project.default_params_from(&source).unwrap();
+        assert_eq!(params.get("modules"), Some(&"core,web".to_owned()));
+
+        // input is read as the type of declared value
+        params.insert("use_docker".to_owned(), "yes".to_owned()).unwrap();
+        assert!(params.insert("port".to_owned(), "not a number".to_owned()).is_err());
+        assert_eq!(params.get_value("use_docker").and_then(|v| v.as_bool()), Some(true));
+        assert_eq!(params.get_value("port").and_then(|v| v.as_integer()), Some(8080));
+        params.insert("port".to_owned(), "9000".to_owned()).unwrap();
+        params.insert("modules".to_owned(), "core, web, db".to_owned()).unwrap();
+
+        let dest = tempdir::TempDir::new("generated-proj").unwrap();
+        project.generate_from(&params, &source, dest.path(), false).unwrap();
+        assert_eq!(fsutils::read_file(&dest.path().join("README.md")).unwrap(),
+                   "docker 9001 [core][web][db] me");
+    }
+
+    #[test]
+    fn memory_source() {
+        let mut source = MemorySource::new();
+        source.insert("template/Rig.toml", TOML)
+            .insert("template/README.md", "# {{ name }}")
+            .insert("template/src/$package$/lib.rs", "// {{ module_name }}")
+            .insert("template/logo.png", vec![0x89, 0x50, 0xff, 0x00])
+            .insert("ignored.txt", "outside of template root");
+
+        let dest = tempdir::TempDir::new("generated-proj").unwrap();
+        let dest = dest.path();
+
+        let project = Project::new(Some("template"), Configuration::Toml, true);
+        let params = project.default_params_from(&source).unwrap();
+        assert_eq!(params.get("package"), Some(&"deep.pkg.path".to_owned()));
+
+        project.generate_from(&params, &source, &dest, false).unwrap();
+
+        assert_eq!(fsutils::read_file(&dest.join("README.md")).unwrap(), "# My Project");
+        assert_eq!(fsutils::read_file(&dest.join("src/deep/pkg/path/lib.rs")).unwrap(),
+                   "// quux");
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 3);
+        assert!(!fsutils::exists(&dest.join("Rig.toml")));
+        assert!(!fsutils::exists(&dest.join("ignored.txt")));
+    }
+
+    #[test]
+    fn builtin_variables() {
+        assert_eq!(builtins::civil_date(0), (1970, 1, 1));
+        assert_eq!(builtins::civil_date(11016), (2000, 2, 29));
+        assert_eq!(builtins::civil_date(17532), (2018, 1, 1));
+
+        let dest = tempdir::TempDir::new("generated-proj").unwrap();
+        let dest = dest.path().join("my-project");
+        let builtins = Builtins::new(&dest,
+                                     "https://github.com/owner/template",
+                                     Some("0123abcd".to_owned()));
+        assert_eq!(builtins.output_dir_name, "my-project");
+        assert_eq!(builtins.date.len(), 10);
+        assert!(builtins.date.starts_with(&builtins.year.to_string()));
+
+        let mut source = MemorySource::new();
+        source.insert("Rig.toml", "name = \"app\"\nheader = \"(c) {{ rig.year }}\"")
+            .insert("LICENSE", "{{ header }} {{ name }}")
+            .insert("CHANGELOG.md", "{{ rig.output_dir_name }} from {{ rig.template_url }}");
+        let project = Project::default();
+        let mut params = project.default_params_from(&source).unwrap();
+        params.set_builtins(builtins.to_table());
+        params.compute_all().unwrap();
+        project.generate_from(&params, &source, &dest, false).unwrap();
+        assert_eq!(fsutils::read_file(&dest.join("LICENSE")).unwrap(),
+                   format!("(c) {} app", builtins.year));
+        assert_eq!(fsutils::read_file(&dest.join("CHANGELOG.md")).unwrap(),
+                   "my-project from https://github.com/owner/template");
+
+        let mut source = MemorySource::new();
+        source.insert("src/main/g8/default.properties", "name=app")
+            .insert("src/main/g8/NOTICE", "$name$ $rig.template_commit$ $rig.version$");
+        let project = Project::new_g8(Some("src/main/g8"));
+        let mut params = project.default_params_from(&source).unwrap();
+        params.set_builtins(builtins.to_table());
+        project.generate_from(&params, &source, &dest.join("g8"), false).unwrap();
+        assert_eq!(fsutils::read_file(&dest.join("g8/NOTICE")).unwrap(),
+                   format!("app 0123abcd {}", builtins.version));
+        assert!(params.keys.iter().all(|k| !k.starts_with("rig")));
+    }
+
+    #[test]
+    fn render_errors() {
+        let dest = tempdir::TempDir::new("generated-proj").unwrap();
+        let project = Project::new(Some("template"), Configuration::Toml, false);
+        let render_error = |source: &MemorySource| {
+            let params = project.default_params_from(source).unwrap();
+            match project.generate_from(&params, source, dest.path(), false) {
+                Err(e) => {
+                    match *e.kind() {
+                        ErrorKind::RenderFailure(ref err) => err.clone(),
+                        ref other => panic!("{}", other),
+                    }
+                }
+                Ok(_) => panic!("rendered"),
+            }
+        };
+
+        let mut source = MemorySource::new();
+        source.insert("template/Rig.toml", TOML)
+            .insert("template/README.md", "# {{ name }}\n\nBy {{ author }}.\n");
+        let err = render_error(&source);
+        assert!(err.path.ends_with("README.md"));
+        assert_eq!((err.line, err.column), (3, 7));
+        assert_eq!(err.snippet, "By {{ author }}.");
+        assert!(err.reason.contains("author"), "{}", err.reason);
+
+        source.insert("template/README.md", "# {{ name }}\n{% if %}\n");
+        let err = render_error(&source);
+        assert_eq!(err.line, 2);
+        assert_eq!(err.snippet, "{% if %}");
+
+        let project = Project::new_g8(Some("template"));
+        let mut source = MemorySource::new();
+        source.insert("template/default.properties", "name=app")
+            .insert("template/build.sbt", "name := \"$name$\"\nversion := $version=1$\n");
+        let params = project.default_params_from(&source).unwrap();
+        let err = project.generate_from(&params, &source, dest.path(), false).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("build.sbt at line 2, column 20"), "{}", message);
+        assert!(message.contains("2 | version := $version=1$\n  |                    ^"),
+                "{}",
+                message);
+    }
+
+    #[test]
+    fn malformed_properties() {
+        let project = Project::new_g8(Some("template"));
+        let mut source = MemorySource::new();
+        source.insert("template/default.properties", "name=app\npackage=com.\\uZZZZ\n");
+        let message = project.default_params_from(&source).unwrap_err().to_string();
+        assert!(message.contains("default.properties"), "{}", message);
+    }
+}
+
+mod location_test {
+
+    extern crate tempdir;
+    extern crate url;
+
+    use rig::config::UserConfig;
+    use rig::errors::Result;
+    use rig::location::{Hosts, Location, Revision};
+    use self::url::Url;
+
+    fn parse(raw: &str) -> Result<Location> {
+        Location::parse(raw, &Hosts::default())
+    }
+
+    #[test]
+    fn github_shorthand() {
+        assert_eq!(parse("lettenj61/rig").unwrap(),
+                   Location::Remote(Url::parse("https://github.com/lettenj61/rig").unwrap(), None));
+    }
+
+    #[test]
+    fn host_prefixes() {
+        assert_eq!(parse("gl:owner/repo").unwrap(),
+                   Location::Remote(Url::parse("https://gitlab.com/owner/repo").unwrap(), None));
+        assert_eq!(parse("bb:owner/repo#dev").unwrap(),
+                   Location::Remote(Url::parse("https://bitbucket.org/owner/repo").unwrap(),
+                                    Some(Revision::Spec("dev".to_owned()))));
+
+        assert_eq!(parse("git@github.com:owner/repo").unwrap(),
+                   Location::Remote(Url::parse("ssh://git@github.com/owner/repo").unwrap(), None));
+        assert_eq!(parse("ssh://git@example.com/owner/repo").unwrap(),
+                   Location::Remote(Url::parse("ssh://git@example.com/owner/repo").unwrap(), None));
+
+        assert!(parse("xx:owner/repo").is_err());
+        assert!(parse("gl:repo").is_err());
+        assert!(parse("ftp://example.com/owner/repo").is_err());
+        assert!(parse("https://example.com/owner/repo").is_ok());
+    }
+
+    #[test]
+    fn user_defined_hosts() {
+        let config = UserConfig::from_toml(r#"
+            default_host = "corp"
+
+            [hosts]
+            corp = "https://git.example.com/"
+
+            [git]
+            token_env = "CORP_TOKEN"
+        "#).unwrap();
+        assert_eq!(config.git.token_env, Some("CORP_TOKEN".to_owned()));
+
+        assert_eq!(Location::parse("corp:platform/service", &config.hosts).unwrap(),
+                   Location::Remote(Url::parse("https://git.example.com/platform/service").unwrap(),
+                                    None));
+        assert_eq!(Location::parse("platform/service", &config.hosts).unwrap(),
+                   Location::Remote(Url::parse("https://git.example.com/platform/service").unwrap(),
+                                    None));
+
+        assert!(UserConfig::from_toml("default_host = \"nowhere\"").is_err());
+    }
+
+    #[test]
+    fn template_aliases() {
+        let config = UserConfig::from_toml(r#"
+            index = "/srv/rig/index.json"
+
+            [aliases]
+            svc = "gl:platform/service-template#v3"
+        "#).unwrap();
+        assert_eq!(config.indexes, vec!["/srv/rig/index.json".to_owned()]);
+
+        assert_eq!(config.expand_alias("svc"), "gl:platform/service-template#v3");
+        assert_eq!(config.expand_alias("owner/project"), "owner/project");
+        assert_eq!(Location::parse(config.expand_alias("svc"), &config.hosts).unwrap(),
+                   Location::Remote(Url::parse("https://gitlab.com/platform/service-template")
+                                        .unwrap(),
+                                    Some(Revision::Spec("v3".to_owned()))));
+    }
+
+    #[test]
+    fn revision_shorthand() {
+        let url = Url::parse("https://github.com/lettenj61/rig").unwrap();
+        assert_eq!(parse("lettenj61/rig#v0.1.0").unwrap(),
+                   Location::Remote(url.clone(), Some(Revision::Spec("v0.1.0".to_owned()))));
+
+        let pinned = parse("lettenj61/rig#v0.1.0")
+            .unwrap()
+            .with_revision(Some(Revision::Branch("master".to_owned())));
+        assert_eq!(pinned, Location::Remote(url, Some(Revision::Branch("master".to_owned()))));
+
+        assert!(parse("lettenj61/rig#").is_err());
+    }
+
+    #[test]
+    fn version_range_shorthand() {
+        let url = Url::parse("https://github.com/lettenj61/rig").unwrap();
+        match parse("lettenj61/rig@^1.2").unwrap() {
+            Location::Remote(u, Some(Revision::Range(req))) => {
+                assert_eq!(u, url);
+                assert_eq!(req.to_string(), "^1.2");
+            }
+            other => panic!("Unexpected location: {:?}", other),
+        }
+
+        assert!(parse("lettenj61/rig@not-a-range").is_err());
+    }
+
+    #[test]
+    fn local_directory() {
+        let dir = tempdir::TempDir::new("rig-location-test").unwrap();
+        let path = dir.path().to_string_lossy().into_owned();
+        assert_eq!(parse(&path).unwrap(),
+                   Location::Local(dir.path().to_path_buf()));
+
+        let url = Url::from_directory_path(dir.path()).unwrap();
+        assert_eq!(parse(url.as_ref()).unwrap(),
+                   Location::Local(dir.path().to_path_buf()));
+    }
+
+    #[test]
+    fn missing_local_directory() {
+        assert!(parse("./no/such/template").is_err());
+    }
+}
+
+mod git_test {
+
+    extern crate git2;
+    extern crate semver;
+    extern crate tempdir;
+    extern crate url;
+
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+
+    use rig::fsutils;
+    use rig::git;
+    use rig::location::Revision;
+    use self::git2::Repository;
+    use self::semver::VersionReq;
+    use self::url::Url;
+    use support;
+
+    #[test]
+    fn clone_revision() {
+        let origin = tempdir::TempDir::new("rig-git-origin").unwrap();
+        let repo = Repository::init(origin.path()).unwrap();
+        let first = support::commit_file(&repo, "Rig.toml", "name = \"first\"");
+        let obj = repo.find_object(first, None).unwrap();
+        repo.tag_lightweight("v1.0.0", &obj, false).unwrap();
+        let second = support::commit_file(&repo, "Rig.toml", "name = \"second\"");
+
+        let url = Url::from_directory_path(origin.path()).unwrap();
+
+        let head = tempdir::TempDir::new("rig-git-head").unwrap();
+        assert_eq!(git::clone(&url, None, head.path(), &Default::default()).unwrap(), second);
+
+        let tag = tempdir::TempDir::new("rig-git-tag").unwrap();
+        let rev = Revision::Tag("v1.0.0".to_owned());
+        assert_eq!(git::clone(&url, Some(&rev), tag.path(), &Default::default()).unwrap(), first);
+        assert_eq!(fsutils::read_file(&tag.path().join("Rig.toml")).unwrap(),
+                   "name = \"first\"");
+
+        let spec = tempdir::TempDir::new("rig-git-spec").unwrap();
+        let rev = Revision::Spec(format!("{}", first));
+        assert_eq!(git::clone(&url, Some(&rev), spec.path(), &Default::default()).unwrap(), first);
+
+        let missing = tempdir::TempDir::new("rig-git-missing").unwrap();
+        let rev = Revision::Tag("v9.9.9".to_owned());
+        assert!(git::clone(&url, Some(&rev), missing.path(), &Default::default()).is_err());
+
+        let settings = git::Settings { progress: true, ..Default::default() };
+        let reported = tempdir::TempDir::new("rig-git-progress").unwrap();
+        assert_eq!(git::clone(&url, None, reported.path(), &settings).unwrap(), second);
+    }
+
+    #[test]
+    fn highest_matching_tag() {
+        let tags = vec!["v1.1.0", "v1.2.0", "1.2.5", "v1.10.0", "v2.0.0", "latest"]
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>();
+
+        let req = VersionReq::parse("^1.2").unwrap();
+        assert_eq!(git::highest_matching(&tags, &req), Some("v1.10.0".to_owned()));
+
+        let req = VersionReq::parse("~1.2").unwrap();
+        assert_eq!(git::highest_matching(&tags, &req), Some("1.2.5".to_owned()));
+
+        let req = VersionReq::parse("^3").unwrap();
+        assert_eq!(git::highest_matching(&tags, &req), None);
+    }
+
+    #[test]
+    fn clone_version_range() {
+        let origin = tempdir::TempDir::new("rig-git-origin").unwrap();
+        let repo = Repository::init(origin.path()).unwrap();
+        for version in &["v1.0.0", "v1.1.0", "v2.0.0"] {
+            let commit = support::commit_file(&repo, "VERSION", version);
+            let obj = repo.find_object(commit, None).unwrap();
+            repo.tag_lightweight(version, &obj, false).unwrap();
+        }
+
+        let url = Url::from_directory_path(origin.path()).unwrap();
+        assert_eq!(git::list_tags(&url, &Default::default()).unwrap(),
+                   vec!["v1.0.0", "v1.1.0", "v2.0.0"]);
+
+        let dest = tempdir::TempDir::new("rig-git-range").unwrap();
+        let rev = Revision::Range(VersionReq::parse("^1").unwrap());
+        git::clone(&url, Some(&rev), dest.path(), &Default::default()).unwrap();
+        assert_eq!(fsutils::read_file(&dest.path().join("VERSION")).unwrap(), "v1.1.0");
+    }
+
+    #[test]
+    fn shallow_clone() {
+        if Command::new("git").arg("--version").output().is_err() {
+            return; // `git` command is not available
+        }
+
+        let origin = tempdir::TempDir::new("rig-git-origin").unwrap();
+        let repo = Repository::init(origin.path()).unwrap();
+        support::commit_file(&repo, "README", "large monorepo");
+        fs::create_dir_all(origin.path().join("templates/svc")).unwrap();
+        support::commit_file(&repo, "templates/svc/Rig.toml", "name = \"svc\"");
+        let tip = support::commit_file(&repo, "templates/svc/Rig.toml", "name = \"tip\"");
+
+        let url = Url::from_directory_path(origin.path()).unwrap();
+        let dest = tempdir::TempDir::new("rig-git-shallow").unwrap();
+        let commit = git::shallow_clone(&url, None, Some("templates/svc"), true, dest.path(),
+                                        &Default::default())
+            .unwrap();
+
+        assert_eq!(commit, tip);
+        assert!(fsutils::exists(dest.path().join(".git/shallow")));
+        assert!(!fsutils::exists(dest.path().join("README")));
+        assert_eq!(fsutils::read_file(&dest.path().join("templates/svc/Rig.toml")).unwrap(),
+                   "name = \"tip\"");
+    }
+
+    #[test]
+    fn filter_support() {
+        assert!(git::supports_filter("git version 2.19.0"));
+        assert!(git::supports_filter("git version 2.20.1 (Apple Git-117)\n"));
+        assert!(git::supports_filter("git version 3.0.0.windows.1"));
+        assert!(!git::supports_filter("git version 2.18.4"));
+        assert!(!git::supports_filter("git version 1.9.5"));
+        assert!(!git::supports_filter("unexpected"));
+    }
+
+    #[test]
+    fn shallow_clone_access() {
+        let url = Url::parse("ssh://git@git.rig-test.invalid/corp/template").unwrap();
+        let settings = git::Settings {
+            ssh_key: Some("/keys/it's".into()),
+            token_env: Some("RIG_TEST_SHALLOW_TOKEN".to_owned()),
+            ..Default::default()
+        };
+        let access = git::CommandAccess::new(&url, &settings).unwrap();
+        assert!(access.configs.is_empty());
+        assert_eq!(access.envs,
+                   vec![("GIT_SSH_COMMAND".to_owned(), "ssh -i '/keys/it'\\''s'".to_owned())]);
+
+        let saved = env::var("https_proxy").ok();
+        env::set_var("https_proxy", "proxy.rig-test.invalid:8080");
+        env::set_var("RIG_TEST_SHALLOW_TOKEN", "secret");
+        let url = Url::parse("https://git.rig-test.invalid/corp/template.git").unwrap();
+        let access = git::CommandAccess::new(&url, &settings);
+        match saved {
+            Some(proxy) => env::set_var("https_proxy", proxy),
+            None => env::remove_var("https_proxy"),
+        }
+        env::remove_var("RIG_TEST_SHALLOW_TOKEN");
+
+        let access = access.unwrap();
+        assert_eq!(access.configs,
+                   vec!["http.proxy=http://proxy.rig-test.invalid:8080/", "credential.helper="]);
+        let askpass = access.envs.iter().find(|e| e.0 == "GIT_ASKPASS").unwrap().1.clone();
+        assert!(!fsutils::read_file(Path::new(&askpass)).unwrap().contains("secret"));
+        if cfg!(unix) {
+            let answer = |prompt: &str| {
+                let mut command = Command::new(&askpass);
+                for &(ref key, ref value) in &access.envs {
+                    command.env(key, value);
+                }
+                let output = command.arg(prompt).output().unwrap();
+                String::from_utf8(output.stdout).unwrap()
+            };
+            assert_eq!(answer("Username for 'https://git.rig-test.invalid': "),
+                       "x-access-token\n");
+            assert_eq!(answer("Password for 'https://x-access-token@git.rig-test.invalid': "),
+                       "secret\n");
+        }
+    }
+
+    #[test]
+    fn proxy_settings() {
+        assert!(git::bypass_proxy("github.com", "localhost,github.com"));
+        assert!(git::bypass_proxy("api.GitHub.com", ".github.com"));
+        assert!(git::bypass_proxy("git.corp.example.com", "*.example.com:443"));
+        assert!(git::bypass_proxy("anything", "*"));
+        assert!(!git::bypass_proxy("notgithub.com", "github.com"));
+
+        assert_eq!(git::parse_proxy("proxy.example.com:8080").map(|u| u.to_string()),
+                   Some("http://proxy.example.com:8080/".to_owned()));
+        let socks = git::parse_proxy("socks5://127.0.0.1:1080").unwrap();
+        assert_eq!((socks.scheme(), socks.port()), ("socks5", Some(1080)));
+        assert!(git::parse_proxy("http://").is_none());
+        assert!(git::find_proxy_url(&Url::parse("ssh://git@github.com/a/b").unwrap()).is_none());
+    }
+
+    #[test]
+    fn url_rewrite() {
+        let rewrites = vec![("https://github.com/".to_owned(),
+                             "https://mirror.corp/github/".to_owned()),
+                            ("https://github.com/corp/".to_owned(),
+                             "ssh://git@git.corp/".to_owned())];
+        let rewrite = |raw: &str| {
+            git::rewrite_url(&Url::parse(raw).unwrap(), &rewrites).to_string()
+        };
+        assert_eq!(rewrite("https://github.com/rust-lang/rust"),
+                   "https://mirror.corp/github/rust-lang/rust");
+        assert_eq!(rewrite("https://github.com/corp/template"),
+                   "ssh://git@git.corp/template");
+        assert_eq!(rewrite("https://gitlab.com/a/b"), "https://gitlab.com/a/b");
+    }
+}
+
+mod cache_test {
+
+    extern crate git2;
+    extern crate tempdir;
+    extern crate url;
+
+    use rig::cache::Cache;
+    use rig::fsutils;
+    use rig::git;
+    use rig::location::Revision;
+    use rig::project::Project;
+    use rig::source::{GitSource, Source};
+    use self::git2::{Repository, Signature};
+    use self::url::Url;
+    use support;
+
+    #[test]
+    fn cache_path() {
+        let cache = Cache::new("/tmp/rig-cache");
+        let url = Url::parse("https://github.com/lettenj61/rig").unwrap();
+        assert_eq!(cache.path_for(&url).to_string_lossy(),
+                   "/tmp/rig-cache/github.com/lettenj61/rig.git");
+
+        let url = Url::parse("ssh://git@example.com:2222/group/../template.git").unwrap();
+        assert_eq!(cache.path_for(&url).to_string_lossy(),
+                   "/tmp/rig-cache/example.com_2222/template.git");
+    }
+
+    #[test]
+    fn update_incrementally() {
+        let origin = tempdir::TempDir::new("rig-cache-origin").unwrap();
+        let repo = Repository::init(origin.path()).unwrap();
+        let first = support::commit_file(&repo, "VERSION", "1");
+        let obj = repo.find_object(first, None).unwrap();
+        repo.tag_lightweight("v1.0.0", &obj, false).unwrap();
+
+        let root = tempdir::TempDir::new("rig-cache").unwrap();
+        let cache = Cache::new(root.path());
+        let url = Url::from_directory_path(origin.path()).unwrap();
+        assert!(cache.get(&url).is_err());
+
+        cache.update(&url, &Default::default()).unwrap();
+        assert!(cache.contains(&url));
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, cache.path_for(&url));
+
+        let second = support::commit_file(&repo, "VERSION", "2");
+        let mirror = cache.update(&url, &Default::default()).unwrap();
+        let mirror_url = Url::from_file_path(&mirror).unwrap();
+
+        let dest = tempdir::TempDir::new("rig-cache-head").unwrap();
+        assert_eq!(git::clone(&mirror_url, None, dest.path(), &Default::default()).unwrap(),
+                   second);
+
+        let dest = tempdir::TempDir::new("rig-cache-tag").unwrap();
+        let rev = Revision::Tag("v1.0.0".to_owned());
+        git::clone(&mirror_url, Some(&rev), dest.path(), &Default::default()).unwrap();
+        assert_eq!(fsutils::read_file(&dest.path().join("VERSION")).unwrap(), "1");
+
+        cache.remove(&url).unwrap();
+        assert!(!cache.contains(&url));
+        cache.clear().unwrap();
+        assert!(!fsutils::exists(root.path()));
+    }
+
+    #[test]
+    fn failed_update_is_not_cached() {
+        let missing = tempdir::TempDir::new("rig-cache-missing").unwrap();
+        let url = Url::from_directory_path(missing.path().join("nothing")).unwrap();
+
+        let root = tempdir::TempDir::new("rig-cache").unwrap();
+        let cache = Cache::new(root.path());
+        assert!(cache.update(&url, &Default::default()).is_err());
+        assert!(!cache.contains(&url));
+        assert!(cache.entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn generate_from_mirror() {
+        let origin = tempdir::TempDir::new("rig-cache-origin").unwrap();
+        let repo = Repository::init(origin.path()).unwrap();
+        support::commit_file(&repo, "Rig.toml", "name = \"mirrored\"");
+        let first = support::commit_file(&repo, "README.md", "{{ name }} 1");
+        let obj = repo.find_object(first, None).unwrap();
+        repo.tag_lightweight("v1.0.0", &obj, false).unwrap();
+        support::commit_file(&repo, "README.md", "{{ name }} 2");
+
+        let root = tempdir::TempDir::new("rig-cache").unwrap();
+        let cache = Cache::new(root.path());
+        let url = Url::from_directory_path(origin.path()).unwrap();
+        let mirror = cache.update(&url, &Default::default()).unwrap();
+
+        let rev = Revision::Tag("v1.0.0".to_owned());
+        let source = GitSource::open(&mirror, Some(&rev)).unwrap();
+        assert_eq!(source.commit(), first);
+        assert!(source.is_file("README.md".as_ref()));
+
+        let project = Project::default();
+        let params = project.default_params_from(&source).unwrap();
+        let dest = tempdir::TempDir::new("rig-cache-generated").unwrap();
+        project.generate_from(&params, &source, dest.path(), false).unwrap();
+        assert_eq!(fsutils::read_file(&dest.path().join("README.md")).unwrap(),
+                   "mirrored 1");
+        assert!(!fsutils::exists(&dest.path().join("Rig.toml")));
+    }
+
+    #[test]
+    fn generate_with_submodule() {
+        let origin = tempdir::TempDir::new("rig-cache-origin").unwrap();
+        let sub_repo = Repository::init(origin.path().join("shared-ci")).unwrap();
+        let sub_commit = support::commit_file(&sub_repo, "ci.yml", "project: {{ name }}");
+
+        // superproject has `ci` submodule, referred by URL relative to its own
+        let repo = Repository::init(origin.path().join("template")).unwrap();
+        let gitmodules = "[submodule \"ci\"]\n\tpath = ci\n\turl = ../shared-ci\n";
+        let mut builder = repo.treebuilder(None).unwrap();
+        builder.insert(".gitmodules", repo.blob(gitmodules.as_bytes()).unwrap(), 0o100644)
+            .unwrap();
+        builder.insert("Rig.toml", repo.blob(b"name = \"sub\"").unwrap(), 0o100644).unwrap();
+        builder.insert("ci", sub_commit, 0o160000).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let sig = Signature::now("rig", "rig@example.com").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "add submodule", &tree, &[]).unwrap();
+
+        let root = tempdir::TempDir::new("rig-cache").unwrap();
+        let cache = Cache::new(root.path());
+        let url = Url::from_directory_path(origin.path().join("template")).unwrap();
+        let mirror = cache.update(&url, &Default::default()).unwrap();
+
+        let mut source = GitSource::open(&mirror, None).unwrap();
+        assert!(source.is_dir("ci".as_ref()));
+        assert!(!source.is_file("ci/ci.yml".as_ref()));
+
+        let fetch = |url: &Url| cache.update(url, &Default::default());
+        source.load_submodules(&url, &fetch).unwrap();
+        assert!(source.is_file("ci/ci.yml".as_ref()));
+
+        let project = Project::default();
+        let params = project.default_params_from(&source).unwrap();
+        let dest = tempdir::TempDir::new("rig-cache-generated").unwrap();
+        project.generate_from(&params, &source, dest.path(), false).unwrap();
+        assert_eq!(fsutils::read_file(&dest.path().join("ci/ci.yml")).unwrap(),
+                   "project: sub");
+    }
+}
+
+mod archive_test {
+
+    extern crate flate2;
+    extern crate tar;
+    extern crate tempdir;
+    extern crate zip;
+
+    use std::io::{Cursor, Write};
+    use std::path::Path;
+
+    use rig::archive::{self, Format};
+    use rig::fsutils;
+    use rig::location::{Hosts, Location};
+    use rig::project::Project;
+    use rig::source::Source;
+    use self::flate2::Compression;
+    use self::flate2::write::GzEncoder;
+
+    fn tar_gz(files: &[(&str, &str)]) -> Vec<u8> {
+        let encoder = GzEncoder::new(Vec::new(), Compression::Default);
+        let mut builder = tar::Builder::new(encoder);
+        for &(path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(path).unwrap();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn archive_format() {
+        assert_eq!(Format::from_name("template-1.0.tar.gz"), Some(Format::TarGz));
+        assert_eq!(Format::from_name("template.TGZ"), Some(Format::TarGz));
+        assert_eq!(Format::from_name("/tmp/template.tar.xz"), Some(Format::TarXz));
+        assert_eq!(Format::from_name("template.zip"), Some(Format::Zip));
+        assert_eq!(Format::from_name("template.git"), None);
+    }
+
+    #[test]
+    fn unpack_tarball() {
+        let content = tar_gz(&[("template-1.0/Rig.toml", "name = \"tarball\""),
+                               ("template-1.0/src/{{ name }}.txt", "{{ name }}")]);
+        let source = archive::unpack(&content, Format::TarGz).unwrap();
+        assert!(source.is_file(Path::new("Rig.toml")));
+
+        let dir = tempdir::TempDir::new("rig-archive").unwrap();
+        let file = dir.path().join("template-1.0.tar.gz");
+        fsutils::write_bytes(&file, &content).unwrap();
+        let location = Location::parse(file.to_str().unwrap(), &Hosts::default()).unwrap();
+        let source = match location {
+            Location::Archive(url) => archive::open(&url).unwrap(),
+            other => panic!("not an archive: {:?}", other),
+        };
+
+        let project = Project::default();
+        let params = project.default_params_from(&source).unwrap();
+        let dest = tempdir::TempDir::new("rig-archive-generated").unwrap();
+        project.generate_from(&params, &source, dest.path(), false).unwrap();
+        assert_eq!(fsutils::read_file(&dest.path().join("src/{{ name }}.txt")).unwrap(),
+                   "tarball");
+    }
+
+    #[test]
+    fn unpack_zip() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for &(path, content) in &[("template/Rig.toml", "name = \"zipped\""),
+                                  ("README.md", "# zipped"),
+                                  ("../escape.txt", "outside")] {
+            writer.start_file(path, zip::write::FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        let content = writer.finish().unwrap().into_inner();
+
+        let source = archive::unpack(&content, Format::Zip).unwrap();
+        assert!(source.is_file(Path::new("template/Rig.toml")));
+        assert!(source.is_file(Path::new("README.md")));
+        assert!(!source.is_file(Path::new("escape.txt")));
+        assert!(source.entries(Path::new("")).unwrap().iter().all(|e| !e.path.starts_with("..")));
+    }
+}
+
+mod index_test {
+
+    use rig::index::Index;
+
+    const INDEX: &'static str = r#"[
+        {
+            "name": "svc",
+            "location": "gl:platform/service-template#v3",
+            "description": "Microservice with CI pipeline",
+            "tags": ["rust", "Docker"]
+        },
+        {
+            "name": "scala-seed",
+            "location": "scala/scala-seed.g8"
+        }
+    ]"#;
+
+    #[test]
+    fn search_index() {
+        let index = Index::from_json(INDEX).unwrap();
+        assert_eq!(index.entries.len(), 2);
+        assert_eq!(index.entries[1].description, "");
+
+        let names = |term: &str| {
+            index.search(term).iter().map(|e| e.name.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(names("docker"), vec!["svc"]);
+        assert_eq!(names("PIPELINE"), vec!["svc"]);
+        assert_eq!(names("g8"), vec!["scala-seed"]);
+        assert_eq!(names("e"), vec!["svc", "scala-seed"]);
+        assert!(names("python").is_empty());
+
+        assert!(Index::from_json(r#"{"name": "svc"}"#).is_err());
+        assert!(Index::from_json(r#"[{"name": "svc"}]"#).is_err());
+    }
+}
+
+mod schema_test {
+
+    extern crate toml;
+
+    use rig::overrides;
+    use rig::schema::ParamType;
+    use rig::template::{Params, Style, Template};
+    use self::toml::value::Value;
+
+    const TOML: &'static str = r#"
+        name = "declared"
+        crate_name = "my_crate"
+
+        [params.license]
+        prompt = "License of the project"
+        help = "SPDX identifier"
+        choices = ["MIT", "Apache-2.0"]
+
+        [params.crate_name]
+        pattern = "[a-z][a-z0-9_]*"
+
+        [params.workers]
+        type = "integer"
+        default = 4
+    "#;
+
+    #[test]
+    fn declared_params() {
+        let params = Params::read_toml(TOML).unwrap();
+        assert_eq!(params.keys, vec!["name", "crate_name", "license", "workers"]);
+        assert!(params.get_value("params").is_none());
+        assert_eq!(params.get("license").unwrap(), "MIT");
+        assert_eq!(params.get_value("workers"), Some(&Value::Integer(4)));
+
+        let license = &params.specs["license"];
+        assert_eq!(license.prompt, Some("License of the project".to_owned()));
+        assert_eq!(license.help, Some("SPDX identifier".to_owned()));
+        assert_eq!(license.kind, ParamType::String);
+        assert_eq!(license.read_input("2"), Ok(Value::String("Apache-2.0".into())));
+        assert_eq!(license.read_input(" MIT "), Ok(Value::String("MIT".into())));
+        assert!(license.read_input("3").is_err());
+        assert!(license.read_input("GPL").is_err());
+
+        let crate_name = &params.specs["crate_name"];
+        assert_eq!(crate_name.read_input("rig_2"), Ok(Value::String("rig_2".into())));
+        assert!(crate_name.read_input("Rig").is_err());
+        assert!(crate_name.read_input("my-rig").is_err());
+
+        let workers = &params.specs["workers"];
+        assert_eq!(workers.read_input("8"), Ok(Value::Integer(8)));
+        assert!(workers.read_input("many").is_err());
+
+        // declared parameters never hold values their declaration rejects
+        let mut params = params;
+        params.insert("workers".to_owned(), "16".to_owned()).unwrap();
+        assert_eq!(params.get_value("workers"), Some(&Value::Integer(16)));
+        let err = params.insert("crate_name".to_owned(), "My Crate".to_owned()).unwrap_err();
+        assert!(err.to_string().contains("crate_name"), "{}", err);
+        assert_eq!(params.get("crate_name").unwrap(), "my_crate");
+
+        params.computed.insert("license".to_owned(), Template::read_str(Style::ST, "$name$"));
+        assert!(params.compute("license").is_err());
+    }
+
+    #[test]
+    fn conditional_params() {
+        let mut params = Params::read_toml(r#"
+            use_docker = false
+            env = "dev"
+
+            [params.docker_registry]
+            default = "registry.example.com"
+            when = "use_docker"
+
+            [params.replicas]
+            type = "integer"
+            when = "use_docker and env != \"dev\""
+
+            [params.broken]
+            default = "never asked"
+            when = "use_docker =="
+        "#).unwrap();
+        assert!(params.is_asked("env").unwrap());
+        assert!(!params.is_asked("docker_registry").unwrap());
+        assert!(!params.is_asked("replicas").unwrap());
+        assert!(overrides::missing(&params, &[]).unwrap().is_empty());
+
+        params.insert("use_docker".to_owned(), "yes".to_owned()).unwrap();
+        assert!(params.is_asked("docker_registry").unwrap());
+        assert!(!params.is_asked("replicas").unwrap());
+        params.insert("env".to_owned(), "prod".to_owned()).unwrap();
+        assert!(params.is_asked("replicas").unwrap());
+        assert_eq!(overrides::missing(&params, &[]).unwrap(), vec!["replicas"]);
+
+        assert!(params.is_asked("broken").is_err());
+    }
+
+    #[test]
+    fn invalid_declaration() {
+        assert!(Params::read_toml("[params.a]\ntype = \"color\"").is_err());
+        assert!(Params::read_toml("[params.a]\npattern = \"(\"").is_err());
+        assert!(Params::read_toml("[params.a]\nchoices = [\"x\"]\ndefault = \"y\"").is_err());
+        assert!(Params::read_toml("[params.a]\ntype = \"integer\"\nchoices = [\"x\"]").is_err());
+        assert!(Params::read_toml("a = \"A\"\n[params.a]\npattern = \"[a-z]+\"").is_err());
+        assert!(Params::read_toml("[params]\na = 1").is_err());
+    }
+}
+
+mod maven_test {
+
+    extern crate tempdir;
+    extern crate url;
+
+    use std::fs;
+
+    use rig::fsutils;
+    use rig::maven::{self, Directive, Settings};
+    use rig::template::Params;
+    use self::url::Url;
+
+    const METADATA: &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <metadata>
+          <groupId>org.scala-lang</groupId>
+          <artifactId>scala-library</artifactId>
+          <versioning>
+            <versions>
+              <version>2.11.8</version>
+              <version>2.12.0-RC2</version>
+              <version>2.12.1</version>
+              <version>2.13.0-M1</version>
+              <version>2.13.0-SNAPSHOT</version>
+            </versions>
+          </versioning>
+        </metadata>"#;
+
+    #[test]
+    fn parse_directive() {
+        assert_eq!(Directive::parse("maven(org.scala-lang, scala-library, stable)"),
+                   Some(Directive::Maven {
+                       group: "org.scala-lang".into(),
+                       artifact: "scala-library".into(),
+                       stable: true,
+                       fallback: None,
+                   }));
+        assert_eq!(Directive::parse("ls(unfiltered, unfiltered, fallback=0.9.1)")
+                       .unwrap()
+                       .fallback(),
+                   Some("0.9.1"));
+        assert_eq!(Directive::parse("maven(org.scala-lang)"), None);
+        assert_eq!(Directive::parse("scala(2.12)"), None);
+        assert_eq!(Directive::parse("2.12.1"), None);
+    }
+
+    #[test]
+    fn latest_version() {
+        let versions = maven::read_metadata(METADATA);
+        assert_eq!(versions.len(), 5);
+        assert_eq!(maven::latest_version(&versions, false), Some("2.13.0-M1".into()));
+        assert_eq!(maven::latest_version(&versions, true), Some("2.12.1".into()));
+
+        let versions = vec!["1.0-RC1".to_owned(), "1.0".to_owned(), "0.10".to_owned()];
+        assert_eq!(maven::latest_version(&versions, false), Some("1.0".into()));
+    }
+
+    #[test]
+    fn resolve_directives() {
+        let remote = tempdir::TempDir::new("rig-maven-remote").unwrap();
+        let dir = remote.path().join("org/scala-lang/scala-library");
+        fs::create_dir_all(&dir).unwrap();
+        fsutils::write_file(&dir.join("maven-metadata.xml"), METADATA).unwrap();
+
+        let local = tempdir::TempDir::new("rig-maven-local").unwrap();
+        fs::create_dir_all(local.path().join("org/scalatest/scalatest_2.12/3.0.1")).unwrap();
+        fs::create_dir_all(local.path().join("org/scalatest/scalatest_2.12/3.0.0")).unwrap();
+
+        let mut settings = Settings {
+            repository: Url::from_directory_path(remote.path()).unwrap().to_string(),
+            local: Some(local.path().to_path_buf()),
+            offline: false,
+        };
+
+        let directives = vec![
+            ("scala_version", "maven(org.scala-lang, scala-library, stable)"),
+            ("scalatest_version", "maven(org.scalatest, scalatest_2.12)"),
+            ("akka_version", "maven(com.typesafe.akka, akka-actor_2.12, fallback=2.4.16)"),
+            ("unfiltered_version", "ls(unfiltered, unfiltered)"),
+        ];
+        let pairs = directives.iter().map(|&(k, v)| (k.to_owned(), v.to_owned()));
+        let mut params = Params::from_pairs(pairs.clone());
+        maven::resolve_params(&mut params, &settings, &[]);
+        assert_eq!(params.get("scala_version").unwrap(), "2.12.1");
+        assert_eq!(params.get("scalatest_version").unwrap(), "3.0.1");
+        assert_eq!(params.get("akka_version").unwrap(), "2.4.16");
+        assert_eq!(params.get("unfiltered_version").unwrap(), "ls(unfiltered, unfiltered)");
+
+        settings.offline = true;
+        let mut params = Params::from_pairs(pairs.clone());
+        maven::resolve_params(&mut params, &settings, &[]);
+        assert_eq!(params.get("scala_version").unwrap(),
+                   "maven(org.scala-lang, scala-library, stable)");
+        assert_eq!(params.get("scalatest_version").unwrap(), "3.0.1");
+
+        // given parameters are not looked up, even in local repository
+        let mut params = Params::from_pairs(pairs);
+        maven::resolve_params(&mut params, &settings, &["scalatest_version".to_owned()]);
+        assert_eq!(params.get("scalatest_version").unwrap(),
+                   "maven(org.scalatest, scalatest_2.12)");
+    }
+}
+
+mod overrides_test {
+
+    use rig::config::UserConfig;
+    use rig::git::Identity;
+    use rig::overrides;
+    use rig::template::Params;
+
+    const USAGE: &'static str = "
+Usage:
+    rig <repository> [options] [--param PARAM]...
+
+Options:
+    --name NAME             Specify project name
+    -P, --param PARAM       Set parameter as `key=value`
+    --dry-run               Show generation process
+";
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn split_command_line() {
+        let (rest, pairs) = overrides::split_args(strings(&["rig",
+                                                            "owner/project",
+                                                            "--name=app",
+                                                            "--organization=com.example",
+                                                            "--param=a=b",
+                                                            "--dry-run",
+                                                            "--",
+                                                            "--skipped=1"]),
+                                                  USAGE);
+        assert_eq!(rest,
+                   strings(&["rig", "owner/project", "--name=app", "--param=a=b", "--dry-run",
+                             "--", "--skipped=1"]));
+        assert_eq!(pairs, vec![("organization".to_owned(), "com.example".to_owned())]);
+
+        assert_eq!(overrides::parse_pair("package=com.example=x"),
+                   Some(("package".to_owned(), "com.example=x".to_owned())));
+        assert_eq!(overrides::parse_pair("=x"), None);
+        assert_eq!(overrides::parse_pair("package"), None);
+    }
+
+    #[test]
+    fn apply_overrides() {
+        let mut params = Params::read_toml(r#"
+            name = "app"
+            scala_version = "2.12.1"
+            docker = false
+
+            [params.license]
+            choices = ["MIT", "Apache-2.0"]
+        "#).unwrap();
+
+        let env = vec![("RIG_PARAM_SCALA_VERSION".to_owned(), "2.11.8".to_owned()),
+                       ("RIG_PARAM_Docker".to_owned(), "no".to_owned()),
+                       ("RIG_PARAM_EXTRA".to_owned(), "ignored".to_owned()),
+                       ("RIG_PARAM_".to_owned(), "ignored".to_owned()),
+                       ("HOME".to_owned(), "/root".to_owned())];
+        let mut pairs = overrides::env_pairs(env, &params.keys);
+        assert_eq!(pairs,
+                   vec![("docker".to_owned(), "no".to_owned()),
+                        ("scala_version".to_owned(), "2.11.8".to_owned())]);
+
+        pairs.push(("docker".to_owned(), "y".to_owned()));
+        pairs.push(("scala_version".to_owned(), "2.12.2".to_owned()));
+        pairs.push(("license".to_owned(), "2".to_owned()));
+        let given = overrides::apply(&mut params, pairs).unwrap();
+        assert_eq!(given, vec!["docker", "scala_version", "license"]);
+        assert_eq!(params.get("scala_version").unwrap(), "2.12.2");
+        assert_eq!(params.get("docker").unwrap(), "true");
+        assert_eq!(params.get("license").unwrap(), "Apache-2.0");
+
+        let invalid = vec![("license".to_owned(), "GPL".to_owned())];
+        assert!(overrides::apply(&mut params, invalid).is_err());
+
+        // a typo is not taken as a new parameter
+        let typo = vec![("scala_verison".to_owned(), "2.12.2".to_owned())];
+        let err = overrides::apply(&mut params, typo).unwrap_err().to_string();
+        assert!(err.contains("scala_verison"), "{}", err);
+        assert!(params.get("scala_verison").is_none());
+    }
+
+    #[test]
+    fn user_profile() {
+        let config = UserConfig::from_toml(r#"
+            [defaults]
+            organization = "com.example"
+            license = "GPL-3.0"
+            email = "team@example.com"
+        "#).unwrap();
+        let identity = Identity {
+            name: Some("Jane Doe".to_owned()),
+            email: Some("jane@example.com".to_owned()),
+        };
+        let profile = config.profile(&identity);
+        assert_eq!(profile["author"].as_str(), Some("Jane Doe"));
+        assert_eq!(profile["email"].as_str(), Some("team@example.com"));
+        assert!(UserConfig::default().profile(&Identity::default()).is_empty());
+
+        let mut params = Params::read_toml(r#"
+            name = "app"
+            author = "Template Author"
+            organization = "org.template"
+
+            [params.license]
+            choices = ["MIT", "Apache-2.0"]
+        "#).unwrap();
+        assert_eq!(overrides::prefill(&mut params, &profile), vec!["author", "organization"]);
+        assert_eq!(params.get("author").unwrap(), "Jane Doe");
+        assert_eq!(params.get("organization").unwrap(), "com.example");
+        assert_eq!(params.get("license").unwrap(), "MIT");
+        assert!(params.get("email").is_none());
+        assert_eq!(params.keys, vec!["name", "author", "organization", "license"]);
+
+        // prefilled values answer parameters without default, when nobody can be asked
+        let mut params = Params::read_toml(r#"
+            name = "app"
+
+            [params.author]
+            prompt = "Author"
+
+            [params.license]
+            prompt = "License"
+        "#).unwrap();
+        assert_eq!(overrides::missing(&params, &[]).unwrap(), vec!["author", "license"]);
+        let filled = overrides::prefill(&mut params, &profile);
+        assert_eq!(filled, vec!["author", "license"]);
+        assert_eq!(overrides::missing(&params, &filled).unwrap(), Vec::<String>::new());
+    }
+}
+
+mod answers_test {
+
+    extern crate tempdir;
+    extern crate toml;
+
+    use rig::answers;
+    use rig::fsutils;
+    use rig::overrides;
+    use rig::template::Params;
+    use self::toml::value::Value;
+
+    const TOML: &'static str = r#"
+        name = "app"
+        docker = false
+        modules = ["core"]
+
+        [params.license]
+        choices = ["MIT", "Apache-2.0"]
+
+        [params.owner]
+        prompt = "Who owns the project"
+
+        [params.team]
+        default = "platform"
+
+        [database]
+        kind = "postgres"
+    "#;
+
+    #[test]
+    fn read_answers() {
+        let dir = tempdir::TempDir::new("rig-answers").unwrap();
+        let json = dir.path().join("answers.json");
+        fsutils::write_file(&json,
+                            r#"{"docker": true, "owner": "me", "workers": 4, "ratio": 0.5,
+                                "nothing": null, "modules": ["core", "web"]}"#)
+            .unwrap();
+        let answers = answers::read(&json).unwrap();
+        assert_eq!(answers,
+                   vec![("docker".to_owned(), Value::Boolean(true)),
+                        ("modules".to_owned(),
+                         Value::Array(vec![Value::String("core".into()),
+                                           Value::String("web".into())])),
+                        ("owner".to_owned(), Value::String("me".into())),
+                        ("ratio".to_owned(), Value::Float(0.5)),
+                        ("workers".to_owned(), Value::Integer(4))]);
+
+        let toml = dir.path().join("answers.toml");
+        fsutils::write_file(&toml, "owner = \"me\"\nlicense = \"MIT\"").unwrap();
+        assert_eq!(answers::read(&toml).unwrap().len(), 2);
+        assert!(answers::from_json("[1, 2]").is_err());
+
+        let mut params = Params::read_toml(TOML).unwrap();
+        assert_eq!(overrides::missing(&params, &[]).unwrap(), vec!["license", "owner"]);
+        let given = overrides::apply_values(&mut params, answers::read(&toml).unwrap()).unwrap();
+        assert!(overrides::missing(&params, &given).unwrap().is_empty());
+
+        let invalid = vec![("license".to_owned(), Value::String("GPL".into()))];
+        assert!(overrides::apply_values(&mut params, invalid).is_err());
+        let unknown = vec![("licence".to_owned(), Value::String("MIT".into()))];
+        assert!(overrides::apply_values(&mut params, unknown).is_err());
+        assert!(!params.keys.contains(&"licence".to_owned()));
+    }
+
+    #[test]
+    fn write_answers() {
+        let mut params = Params::read_toml(TOML).unwrap();
+        params.insert("owner".to_owned(), "me".to_owned()).unwrap();
+        let written = answers::to_toml(&params).unwrap();
+        assert_eq!(written,
+                   "name = \"app\"\ndocker = false\nmodules = [\"core\"]\nlicense = \"MIT\"\n\
+                    owner = \"me\"\nteam = \"platform\"\n\n[database]\nkind = \"postgres\"\n");
+
+        let mut replayed = Params::read_toml(TOML).unwrap();
+        overrides::apply_values(&mut replayed, answers::from_toml(&written).unwrap()).unwrap();
+        assert_eq!(replayed.param_map, params.param_map);
+
+        // arrays of tables come last too, or values after them would belong to them
+        let mut params = Params::read_toml(r#"
+            name = "app"
+
+            [[servers]]
+            host = "a"
+        "#).unwrap();
+        params.insert("port".to_owned(), "80".to_owned()).unwrap();
+        let written = answers::to_toml(&params).unwrap();
+        assert_eq!(written, "name = \"app\"\nport = \"80\"\n\n[[servers]]\nhost = \"a\"\n");
+        assert_eq!(answers::from_toml(&written).unwrap().len(), 3);
+    }
+}
+
+mod progress_test {
+
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+    use std::str;
+
+    use rig::progress::Reporter;
+
+    /// Writer whose output can be read after it is given to `Reporter`.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Output {
+        fn text(&self) -> String {
+            str::from_utf8(&self.0.borrow()).unwrap().to_owned()
+        }
+    }
+
+    #[test]
+    fn log_lines() {
+        let out = Output::default();
+        let mut reporter = Reporter::to_writer("Generating app", false, Box::new(out.clone()));
+        reporter.files(0, 0);
+        reporter.files(1, 4);
+        // lines are written every few seconds, except the last one
+        reporter.files(2, 4);
+        reporter.files(4, 4);
+        reporter.files(4, 4);
+        assert_eq!(out.text(),
+                   "Generating app: writing files 25% (1/4)\n\
+                    Generating app: writing files 100% (4/4)\n");
+    }
+
+    #[test]
+    fn terminal_line() {
+        let out = Output::default();
+        let mut reporter = Reporter::to_writer("Generating app", true, Box::new(out.clone()));
+        reporter.files(1, 2);
+        reporter.files(2, 2);
+        assert_eq!(out.text(),
+                   "\rGenerating app: writing files 50% (1/2)\x1b[K\
+                    \rGenerating app: writing files 100% (2/2)\x1b[K\n");
+    }
 }